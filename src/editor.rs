@@ -1,11 +1,21 @@
 use regex::Regex;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
 use std::fmt::{self, Write};
 use std::fs;
-use std::path::{Path, PathBuf};
+use std::io::{Read, Write as _};
+use std::ops::Range;
+use std::path::{Component, Path, PathBuf};
 use std::str::FromStr;
+use std::time::{Duration, Instant};
 use thiserror::Error;
 
+use crate::history::History;
+use crate::lines::{split_lines, Line, LineFormat};
+use crate::patch::{self, HunkResult, Tolerance};
+use ignore::overrides::{Override, OverrideBuilder};
+
 /// Commands supported by the editor
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Command {
@@ -15,6 +25,16 @@ pub enum Command {
     Insert,
     Delete,
     UndoEdit,
+    MultiEdit,
+    Transaction,
+    ApplyPatch,
+    Rename,
+    Copy,
+    Remove,
+    Mkdir,
+    ReplaceLines,
+    Search,
+    Find,
 }
 
 impl FromStr for Command {
@@ -28,7 +48,20 @@ impl FromStr for Command {
             "insert" => Ok(Command::Insert),
             "delete" => Ok(Command::Delete),
             "undo_edit" => Ok(Command::UndoEdit),
-            _ => Err(EditorError::UnknownCommand(s.to_string())),
+            "multi_edit" => Ok(Command::MultiEdit),
+            "transaction" => Ok(Command::Transaction),
+            "apply_patch" => Ok(Command::ApplyPatch),
+            "rename" => Ok(Command::Rename),
+            "copy" => Ok(Command::Copy),
+            "remove" => Ok(Command::Remove),
+            "mkdir" => Ok(Command::Mkdir),
+            "replace_lines" => Ok(Command::ReplaceLines),
+            "search" => Ok(Command::Search),
+            "find" => Ok(Command::Find),
+            _ => Err(EditorError::UnknownCommand(
+                s.to_string(),
+                ToolVersion::default(),
+            )),
         }
     }
 }
@@ -42,11 +75,108 @@ impl fmt::Display for Command {
             Command::Insert => "insert",
             Command::Delete => "delete",
             Command::UndoEdit => "undo_edit",
+            Command::MultiEdit => "multi_edit",
+            Command::Transaction => "transaction",
+            Command::ApplyPatch => "apply_patch",
+            Command::Rename => "rename",
+            Command::Copy => "copy",
+            Command::Remove => "remove",
+            Command::Mkdir => "mkdir",
+            Command::ReplaceLines => "replace_lines",
+            Command::Search => "search",
+            Command::Find => "find",
         };
         write!(f, "{}", cmd_str)
     }
 }
 
+impl Command {
+    pub const ALL: [Command; 16] = [
+        Command::View,
+        Command::Create,
+        Command::StrReplace,
+        Command::Insert,
+        Command::Delete,
+        Command::UndoEdit,
+        Command::MultiEdit,
+        Command::Transaction,
+        Command::ApplyPatch,
+        Command::Rename,
+        Command::Copy,
+        Command::Remove,
+        Command::Mkdir,
+        Command::ReplaceLines,
+        Command::Search,
+        Command::Find,
+    ];
+}
+
+/// Versions of Anthropic's text editor tool, which differ in the commands and
+/// parameters they accept
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ToolVersion {
+    #[default]
+    TextEditor20250124,
+    TextEditor20250429,
+    TextEditor20250728,
+}
+
+impl ToolVersion {
+    /// The name Claude uses for the tool in this version
+    pub fn tool_name(&self) -> &'static str {
+        match self {
+            ToolVersion::TextEditor20250124 => "str_replace_editor",
+            ToolVersion::TextEditor20250429 | ToolVersion::TextEditor20250728 => {
+                "str_replace_based_edit_tool"
+            }
+        }
+    }
+
+    pub fn supports(&self, command: &Command) -> bool {
+        match command {
+            Command::UndoEdit => *self == ToolVersion::TextEditor20250124,
+            _ => true,
+        }
+    }
+
+    pub fn supports_max_characters(&self) -> bool {
+        *self == ToolVersion::TextEditor20250728
+    }
+
+    fn allowed_commands(&self) -> String {
+        Command::ALL
+            .iter()
+            .filter(|command| self.supports(command))
+            .map(|command| command.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+impl FromStr for ToolVersion {
+    type Err = EditorError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text_editor_20250124" => Ok(ToolVersion::TextEditor20250124),
+            "text_editor_20250429" => Ok(ToolVersion::TextEditor20250429),
+            "text_editor_20250728" => Ok(ToolVersion::TextEditor20250728),
+            _ => Err(EditorError::UnknownToolVersion(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for ToolVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let version_str = match self {
+            ToolVersion::TextEditor20250124 => "text_editor_20250124",
+            ToolVersion::TextEditor20250429 => "text_editor_20250429",
+            ToolVersion::TextEditor20250728 => "text_editor_20250728",
+        };
+        write!(f, "{}", version_str)
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum EditorError {
     #[error("The path {0} does not exist. Please provide a valid path.")]
@@ -66,22 +196,40 @@ pub enum EditorError {
     #[error("{0}")]
     StrReplace(String),
 
+    #[error("No edit history found for {0}.")]
+    NoEditHistory(PathBuf),
+
     #[error(
-        "The undo_edit command is not implemented in this CLI. Please use git for version control."
+        "The undo_edit command requires an edit history. Run the CLI with a state directory (--state-dir) to enable it."
     )]
-    UndoNotImplemented,
+    HistoryDisabled,
 
     #[error("File already exists at: {0}. Cannot overwrite files using command `create`.")]
     FileAlreadyExists(PathBuf),
 
+    #[error("Parameter `path` is required for command: {0}")]
+    MissingPath(Command),
+
+    #[error("Parameter `new_path` is required for command: {0}")]
+    MissingNewPath(Command),
+
+    #[error("The path {0} already exists. Choose a path that does not exist yet.")]
+    PathAlreadyExists(PathBuf),
+
+    #[error("The directory {0} is not empty. Set `recursive: true` to remove it along with everything in it.")]
+    DirectoryNotEmpty(PathBuf),
+
     #[error("Parameter `file_text` is required for command: create")]
     MissingFileText,
 
     #[error("Parameter `insert_line` is required for command: insert")]
     MissingInsertLine,
 
-    #[error("Parameter `new_str` is required for command: insert")]
-    MissingNewStr,
+    #[error("Parameter `new_str` is required for command: {0}")]
+    MissingNewStr(Command),
+
+    #[error("Parameter `line_range` is required for command: replace_lines")]
+    MissingLineRange,
 
     #[error("Parameter `old_str` is required for command: str_replace")]
     MissingOldStr,
@@ -89,11 +237,68 @@ pub enum EditorError {
     #[error("Parameter `delete_range` is required for command: delete")]
     MissingDeleteRange,
 
+    #[error("Parameter `edits` is required for command: multi_edit")]
+    MissingEdits,
+
+    #[error("edits[{0}] failed: {1} No changes were written to the file.")]
+    MultiEdit(usize, Box<EditorError>),
+
+    #[error("Parameter `operations` is required for command: transaction")]
+    MissingOperations,
+
+    #[error("operations[{0}] failed: {1} No changes were written to any file.")]
+    Transaction(usize, Box<EditorError>),
+
+    #[error("The {0} command cannot be used inside a transaction. Only create, str_replace, insert, delete, multi_edit, apply_patch and replace_lines can.")]
+    NotTransactional(Command),
+
+    #[error("Failed to write {0}: {1}. The files already written by this request were restored.")]
+    CommitFailed(PathBuf, std::io::Error),
+
+    #[error("Parameter `patch` is required for command: apply_patch")]
+    MissingPatch,
+
+    #[error("Invalid patch: {0}")]
+    InvalidPatch(String),
+
+    #[error("The patch could not be applied and no changes were written.\n{0}\nView the files to check their current contents, then fix the rejected hunks and retry.")]
+    PatchRejected(String),
+
+    #[error("Parameter `pattern` is required for command: {0}")]
+    MissingPattern(Command),
+
+    #[error("The path {0} is not a directory. The {1} command only works on directories.")]
+    NotADirectory(PathBuf, Command),
+
+    #[error("The path {0} is a directory. The {1} command only works on files; view, rename, remove, search, find and apply_patch accept directories.")]
+    IsADirectory(PathBuf, Command),
+
+    #[error("Invalid glob pattern: {0}")]
+    InvalidGlob(String),
+
     #[error("Invalid regex pattern: {0}")]
     InvalidRegex(String),
 
-    #[error("Unrecognized command {0}. The allowed commands for the str_replace_editor tool are: view, create, str_replace, insert, delete, undo_edit")]
-    UnknownCommand(String),
+    #[error("Unrecognized command {0}. The allowed commands for the {tool} tool are: {allowed}", tool = .1.tool_name(), allowed = .1.allowed_commands())]
+    UnknownCommand(String, ToolVersion),
+
+    #[error("Unknown tool version {0}. The supported versions are: text_editor_20250124, text_editor_20250429, text_editor_20250728")]
+    UnknownToolVersion(String),
+
+    #[error("Parameter `{0}` is not supported by the {1} tool")]
+    UnsupportedParameter(&'static str, ToolVersion),
+
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+
+    #[error("The {0} command is not allowed because the editor is in read-only mode. Only commands that do not modify files, such as view, can be used.")]
+    ReadOnly(Command),
+
+    #[error("The file {0} has been modified since it was last viewed. View the file again to see its current contents and content hash, then retry the edit.")]
+    FileChanged(PathBuf),
+
+    #[error("The path {0} is outside of the workspace root {1}. Only paths inside the workspace can be used.")]
+    OutsideRoot(PathBuf, PathBuf),
 
     #[error(transparent)]
     Io(#[from] std::io::Error),
@@ -103,12 +308,20 @@ pub enum EditorError {
 pub struct Input {
     #[serde(deserialize_with = "deserialize_command")]
     pub command: Command,
+    // Not used by `transaction`, whose operations each name their own path
+    #[serde(default)]
     pub path: String,
     #[serde(default)]
     pub view_range: Option<Vec<i32>>,
     #[serde(default)]
     pub max_depth: Option<usize>,
     #[serde(default)]
+    pub max_characters: Option<usize>,
+    #[serde(default)]
+    pub format: Option<ListFormat>,
+    #[serde(default)]
+    pub max_entries: Option<usize>,
+    #[serde(default)]
     pub old_str: Option<String>,
     #[serde(default)]
     pub new_str: Option<String>,
@@ -117,11 +330,113 @@ pub struct Input {
     #[serde(default)]
     pub file_text: Option<String>,
     #[serde(default)]
+    pub new_path: Option<String>,
+    #[serde(default)]
+    pub pattern: Option<String>,
+    #[serde(default)]
+    pub include: Option<Vec<String>>,
+    #[serde(default)]
+    pub exclude: Option<Vec<String>>,
+    #[serde(default)]
+    pub context_lines: Option<usize>,
+    #[serde(default)]
+    pub max_results: Option<usize>,
+    #[serde(default)]
+    pub sort_by: Option<SortOrder>,
+    #[serde(default)]
+    pub recursive: Option<bool>,
+    #[serde(default)]
     pub delete_range: Option<Vec<i32>>,
     #[serde(default)]
     pub allow_multi: Option<bool>,
     #[serde(default)]
     pub use_regex: Option<bool>,
+    #[serde(default)]
+    pub match_mode: Option<MatchMode>,
+    #[serde(default)]
+    pub occurrence: Option<usize>,
+    #[serde(default)]
+    pub line_range: Option<Vec<i32>>,
+    #[serde(default)]
+    pub max_locations: Option<usize>,
+    #[serde(default)]
+    pub edits: Option<Vec<EditOperation>>,
+    #[serde(default)]
+    pub operations: Option<Vec<Input>>,
+    #[serde(default)]
+    pub patch: Option<String>,
+    #[serde(default)]
+    pub fuzz: Option<usize>,
+    #[serde(default)]
+    pub max_offset: Option<usize>,
+    #[serde(default)]
+    pub snippet_lines: Option<usize>,
+    #[serde(default)]
+    pub expected_hash: Option<String>,
+    #[serde(default)]
+    pub dry_run: Option<bool>,
+    #[serde(default)]
+    pub diff: Option<bool>,
+    #[serde(default)]
+    pub diff_context: Option<usize>,
+}
+
+/// A single replacement within a `multi_edit`, with the same meaning as the
+/// matching `str_replace` parameters
+#[derive(Debug, Clone, Deserialize)]
+pub struct EditOperation {
+    pub old_str: String,
+    #[serde(default)]
+    pub new_str: Option<String>,
+    #[serde(default)]
+    pub allow_multi: Option<bool>,
+    #[serde(default)]
+    pub use_regex: Option<bool>,
+    #[serde(default)]
+    pub match_mode: Option<MatchMode>,
+    #[serde(default)]
+    pub occurrence: Option<usize>,
+    #[serde(default)]
+    pub line_range: Option<Vec<i32>>,
+}
+
+/// How directory `view` lists its entries
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ListFormat {
+    /// One absolute path per line
+    #[default]
+    Paths,
+    /// Paths with a trailing `/` for directories, and file sizes and line
+    /// counts
+    Long,
+    /// Names indented under their directories, relative to the viewed path
+    Tree,
+}
+
+/// The order of the files returned by `find`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    /// Most recently modified first
+    #[default]
+    Modified,
+    /// By path
+    Name,
+}
+
+/// How `old_str` is compared with the file when it does not occur exactly
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchMode {
+    /// Only exact matches are replaced
+    #[default]
+    Exact,
+    /// Whole lines match if they differ only in trailing whitespace
+    IgnoreTrailingWs,
+    /// Whole lines match if they differ only in leading and trailing
+    /// whitespace, and `new_str` is re-indented to fit the matched lines
+    IndentationInsensitive,
 }
 
 // Custom deserializer for Command enum
@@ -133,11 +448,59 @@ where
     Command::from_str(&s).map_err(serde::de::Error::custom)
 }
 
+// Custom deserializer for an optional ToolVersion
+fn deserialize_tool_version<'de, D>(deserializer: D) -> Result<Option<ToolVersion>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let s = Option::<String>::deserialize(deserializer)?;
+    s.map(|s| ToolVersion::from_str(&s).map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+/// A single request: either `{"input": {...}}`, or a Messages API `tool_use`
+/// content block, whose `id` is echoed back as the `tool_use_id` of the result.
 #[derive(Debug, Deserialize)]
 pub struct Request {
+    #[serde(default)]
+    pub id: Option<String>,
+    /// Overrides the editor's tool version for this request
+    #[serde(default, deserialize_with = "deserialize_tool_version")]
+    pub tool_version: Option<ToolVersion>,
     pub input: Input,
 }
 
+/// Parses a single JSON request.
+///
+/// The command is checked before the rest of the request so that an unknown
+/// command is reported with the same message the tool itself would give for
+/// the request's tool version, or `default_version` if it does not name one.
+pub fn parse_request(s: &str, default_version: ToolVersion) -> Result<Request, EditorError> {
+    let value: serde_json::Value =
+        serde_json::from_str(s).map_err(|e| EditorError::InvalidRequest(e.to_string()))?;
+
+    let version = match value.get("tool_version").and_then(|v| v.as_str()) {
+        Some(version) => ToolVersion::from_str(version)?,
+        None => default_version,
+    };
+
+    if let Some(command) = value
+        .get("input")
+        .and_then(|input| input.get("command"))
+        .and_then(|command| command.as_str())
+    {
+        match Command::from_str(command) {
+            Ok(command) if !version.supports(&command) => {
+                return Err(EditorError::UnknownCommand(command.to_string(), version));
+            }
+            Ok(_) => {}
+            Err(_) => return Err(EditorError::UnknownCommand(command.to_string(), version)),
+        }
+    }
+
+    serde_json::from_value(value).map_err(|e| EditorError::InvalidRequest(e.to_string()))
+}
+
 #[derive(Debug, Serialize)]
 pub struct CliResult {
     pub content: String,
@@ -161,6 +524,27 @@ impl CliResult {
     }
 }
 
+/// A Messages API `tool_result` content block.
+#[derive(Debug, Serialize)]
+pub struct ToolResult {
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub tool_use_id: String,
+    pub content: String,
+    pub is_error: bool,
+}
+
+impl ToolResult {
+    pub fn new(tool_use_id: String, result: CliResult) -> Self {
+        Self {
+            kind: "tool_result",
+            tool_use_id,
+            content: result.content,
+            is_error: result.is_error.unwrap_or(false),
+        }
+    }
+}
+
 // Method that validates paths based on command type
 pub fn validate_path(path: &Path, command: &Command) -> Result<(), EditorError> {
     // Check if it's an absolute path
@@ -175,16 +559,26 @@ pub fn validate_path(path: &Path, command: &Command) -> Result<(), EditorError>
                 return Err(EditorError::FileAlreadyExists(path.to_path_buf()));
             }
         }
+        Command::Mkdir => {
+            if path.exists() {
+                return Err(EditorError::PathAlreadyExists(path.to_path_buf()));
+            }
+        }
         _ => {
             // For other commands, file should exist
             if !path.exists() {
                 return Err(EditorError::PathNotFound(path.to_path_buf()));
             }
 
-            // Check if directory for non-view command
-            if path.is_dir() && *command != Command::View {
-                return Err(EditorError::InvalidRange(
-                    format!("The path {} is a directory and only the `view` command can be used on directories", path.display())
+            // Check if directory for commands that only work on files
+            let directories_allowed = matches!(
+                command,
+                Command::View | Command::Rename | Command::Remove | Command::Search | Command::Find
+            );
+            if path.is_dir() && !directories_allowed {
+                return Err(EditorError::IsADirectory(
+                    path.to_path_buf(),
+                    command.clone(),
                 ));
             }
         }
@@ -193,128 +587,914 @@ pub fn validate_path(path: &Path, command: &Command) -> Result<(), EditorError>
     Ok(())
 }
 
-pub fn handle_command(input: Input) -> Result<String, EditorError> {
-    let path = PathBuf::from(&input.path);
+/// Checks that `path` resolves to a location inside `root`.
+///
+/// Symlinks and `..` components are resolved first, so neither can be used to
+/// escape the root. Parts of the path that do not exist yet (such as the
+/// parent directories `create` would make) are resolved lexically.
+pub fn confine_path(path: &Path, root: &Path) -> Result<(), EditorError> {
+    if !path.is_absolute() {
+        return Err(EditorError::NotAbsolutePath(path.to_path_buf()));
+    }
 
-    match input.command {
-        Command::View => view(&path, input.view_range.as_deref(), input.max_depth),
-        Command::Create => {
-            let file_text = input.file_text.ok_or(EditorError::MissingFileText)?;
-            create(&path, &file_text)
+    let root = root.canonicalize()?;
+    if !resolve_path(path)?.starts_with(&root) {
+        return Err(EditorError::OutsideRoot(path.to_path_buf(), root));
+    }
+
+    Ok(())
+}
+
+// Resolve an absolute path component by component, following symlinks in the
+// part of the path that exists, including dangling symlinks
+fn resolve_path(path: &Path) -> std::io::Result<PathBuf> {
+    let mut resolved = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::Prefix(_) | Component::RootDir => resolved.push(component),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            Component::Normal(name) => {
+                resolved.push(name);
+                match resolved.canonicalize() {
+                    Ok(canonical) => resolved = canonical,
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                        if resolved.is_symlink() {
+                            let target = fs::read_link(&resolved)?;
+                            resolved.pop();
+                            resolved = resolve_path(&resolved.join(target))?;
+                        }
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
         }
-        Command::StrReplace => {
-            let old_str = input.old_str.ok_or(EditorError::MissingOldStr)?;
-            let new_str = input.new_str.unwrap_or_default();
-            let allow_multi = input.allow_multi.unwrap_or(false);
-            let use_regex = input.use_regex.unwrap_or(false);
-            str_replace(&path, &old_str, &new_str, allow_multi, use_regex)
+    }
+
+    Ok(resolved)
+}
+
+/// Session state shared across requests.
+#[derive(Debug, Default)]
+pub struct Editor {
+    /// Where previous file contents are recorded for `undo_edit`. Edits are
+    /// not recorded when this is `None`.
+    pub history: Option<History>,
+    /// The tool version used for requests that do not name one
+    pub tool_version: ToolVersion,
+    /// When set, every path must resolve to somewhere inside this directory
+    pub root: Option<PathBuf>,
+    /// Reject every command that would modify the filesystem
+    pub read_only: bool,
+    /// Context lines shown around each edit in result snippets, for requests
+    /// that do not set `snippet_lines` (defaults to 4)
+    pub snippet_lines: Option<usize>,
+    /// Preview edits without writing anything, unless a request says
+    /// otherwise
+    pub dry_run: bool,
+    /// Include a unified diff in edit results unless a request says otherwise
+    pub diff: bool,
+    /// Context lines around each diff hunk, for requests that do not set
+    /// `diff_context` (defaults to 3)
+    pub diff_context: Option<usize>,
+}
+
+impl Editor {
+    pub fn handle_request(&self, request: Request) -> Result<String, EditorError> {
+        let version = request.tool_version.unwrap_or(self.tool_version);
+        let mut input = request.input;
+        let command = input.command.clone();
+
+        let diff_context = input
+            .diff_context
+            .or(self.diff_context)
+            .unwrap_or(DEFAULT_DIFF_CONTEXT);
+        let dry_run = input.dry_run.unwrap_or(self.dry_run);
+        // A preview is mostly useful for its diff
+        let show_diff = input.diff.unwrap_or(self.diff || dry_run);
+        let snippet_lines = input
+            .snippet_lines
+            .or(self.snippet_lines)
+            .unwrap_or(SNIPPET_LINES);
+        let expected_hash = input.expected_hash.clone();
+
+        let outcome = if command == Command::Transaction {
+            self.check_command(&command, version)?;
+            let operations = input
+                .operations
+                .take()
+                .filter(|operations| !operations.is_empty())
+                .ok_or(EditorError::MissingOperations)?;
+            self.transaction(operations, version, snippet_lines)?
+        } else {
+            self.prepare(&Overlay::default(), input, version, snippet_lines)?
+        };
+
+        let mut history_error = None;
+        if !dry_run {
+            commit(&outcome.changes)?;
+            for change in &outcome.path_changes {
+                change.apply()?;
+            }
+
+            // Record the previous contents so the edit can be undone. Undoing
+            // an edit is not itself recorded, but uses up the entry it
+            // restored, now that the restore has been written. The changes
+            // are already written, so a failure here must not be reported as
+            // a failed edit.
+            if let Some(history) = &self.history {
+                for change in &outcome.changes {
+                    let updated = if command == Command::UndoEdit {
+                        history.pop(&change.path).map(drop)
+                    } else {
+                        history.record(&change.path, change.before.as_deref())
+                    };
+                    if let Err(e) = updated {
+                        history_error = Some(e);
+                    }
+                }
+            }
         }
-        Command::Insert => {
-            let insert_line = input.insert_line.ok_or(EditorError::MissingInsertLine)?;
-            let new_str = input.new_str.ok_or(EditorError::MissingNewStr)?;
-            insert(&path, insert_line, &new_str)
+
+        let changed = !outcome.is_empty();
+        let mut content = outcome.content;
+        if dry_run && changed {
+            content = format!(
+                "This is a dry run and no changes were written. Here's a preview of the result:\n\n{}",
+                content
+            );
         }
-        Command::Delete => {
-            let delete_range = input.delete_range.ok_or(EditorError::MissingDeleteRange)?;
-            delete(&path, &delete_range)
+        if show_diff && !outcome.changes.is_empty() {
+            content.push_str("\n\nHere's the diff of the changes:\n");
+            for change in &outcome.changes {
+                content.push_str(&unified_diff(change, diff_context));
+            }
         }
-        Command::UndoEdit => Err(EditorError::UndoNotImplemented),
+        // Let the model chain further guarded edits without another view
+        if expected_hash.is_some() && !dry_run && command != Command::Transaction {
+            if let Some(after) = outcome.changes.iter().find_map(|c| c.after.as_ref()) {
+                let _ = write!(content, "\nContent hash: {}", content_hash(after));
+            }
+        }
+        if let Some(e) = history_error {
+            let _ = write!(
+                content,
+                "\n\nWarning: the changes were written, but the edit history could not be updated, so undo_edit may not revert them: {}",
+                e
+            );
+        }
+
+        Ok(content)
     }
-}
 
-pub fn insert(path: &Path, insert_line: i32, new_str: &str) -> Result<String, EditorError> {
-    validate_path(path, &Command::Insert)?;
+    // Checks that depend only on the command and the editor's configuration
+    fn check_command(&self, command: &Command, version: ToolVersion) -> Result<(), EditorError> {
+        if !version.supports(command) {
+            return Err(EditorError::UnknownCommand(command.to_string(), version));
+        }
+        if self.read_only && is_mutating(command) {
+            return Err(EditorError::ReadOnly(command.clone()));
+        }
+        Ok(())
+    }
 
-    // Path validation already handles directories
+    // Validate a single command and compute its changes against `files`,
+    // without writing anything
+    fn prepare(
+        &self,
+        files: &Overlay,
+        input: Input,
+        version: ToolVersion,
+        snippet_lines: usize,
+    ) -> Result<Outcome, EditorError> {
+        let command = input.command.clone();
+        self.check_command(&command, version)?;
+        if input.max_characters.is_some() && !version.supports_max_characters() {
+            return Err(EditorError::UnsupportedParameter("max_characters", version));
+        }
 
-    let content = fs::read_to_string(path)?;
-    let lines: Vec<_> = content.lines().collect();
+        if input.path.is_empty() {
+            return Err(EditorError::MissingPath(command));
+        }
+        let path = PathBuf::from(&input.path);
+        if let Some(root) = &self.root {
+            confine_path(&path, root)?;
+            // Checked up front too, so that `rename` and `copy` cannot reveal
+            // what exists outside the root
+            if let Some(new_path) = &input.new_path {
+                confine_path(Path::new(new_path), root)?;
+            }
+        }
 
-    if insert_line < 0 || insert_line > lines.len() as i32 {
-        return Err(EditorError::InvalidRange(format!(
-            "Invalid `insert_line` parameter: {}. It should be within the range of lines of the file: [0, {}]",
-            insert_line,
-            lines.len()
-        )));
+        // Refuse to edit a file that changed since the model last viewed it
+        if let Some(expected) = &input.expected_hash {
+            if is_mutating(&command) {
+                let current = files.read(&path).ok();
+                if current.map(|content| content_hash(&content)).as_deref() != Some(expected.trim())
+                {
+                    return Err(EditorError::FileChanged(path));
+                }
+            }
+        }
+
+        let outcome = dispatch(
+            files,
+            input,
+            self.history.as_ref(),
+            self.root.as_deref(),
+            snippet_lines,
+        )?;
+        // Patches and renames can change files other than `path`
+        if let Some(root) = &self.root {
+            for change in &outcome.changes {
+                confine_path(&change.path, root)?;
+            }
+            for path in outcome.path_changes.iter().flat_map(PathChange::paths) {
+                confine_path(path, root)?;
+            }
+        }
+        Ok(outcome)
     }
 
-    let mut new_lines = lines.clone();
-    new_lines.insert(insert_line as usize, new_str);
-    let new_content = new_lines.join("\n") + "\n";
+    // Stage every operation in order, each one seeing the files as the
+    // previous operations left them, so nothing is written unless all of them
+    // succeed
+    fn transaction(
+        &self,
+        operations: Vec<Input>,
+        version: ToolVersion,
+        snippet_lines: usize,
+    ) -> Result<Outcome, EditorError> {
+        let count = operations.len();
+        let mut files = Overlay::default();
+        let mut changes: Vec<FileChange> = Vec::new();
+        let mut messages = Vec::new();
+
+        for (index, operation) in operations.into_iter().enumerate() {
+            let failed = |e| EditorError::Transaction(index, Box::new(e));
+            if !matches!(
+                operation.command,
+                Command::Create
+                    | Command::StrReplace
+                    | Command::Insert
+                    | Command::Delete
+                    | Command::MultiEdit
+                    | Command::ApplyPatch
+                    | Command::ReplaceLines
+            ) {
+                return Err(failed(EditorError::NotTransactional(operation.command)));
+            }
 
-    fs::write(path, &new_content)?;
+            let snippet_lines = operation.snippet_lines.unwrap_or(snippet_lines);
+            let outcome = self
+                .prepare(&files, operation, version, snippet_lines)
+                .map_err(failed)?;
+
+            for change in outcome.changes {
+                files.stage(&change);
+                // Each file is written once, going straight to its final state
+                match changes.iter_mut().find(|c| c.path == change.path) {
+                    Some(existing) => existing.after = change.after,
+                    None => changes.push(change),
+                }
+            }
+            messages.push(format!("operations[{}]: {}", index, outcome.content));
+        }
 
-    // Calculate context for the edit
-    let context_start = (insert_line as usize).saturating_sub(4);
-    let mut context = String::new();
-    new_lines
-        .iter()
-        .enumerate()
-        .skip(context_start)
-        .take(8)
-        .fold(&mut context, |acc, (i, line)| {
-            let _ = writeln!(acc, "{:6}\t{}", i + 1, line);
-            acc
-        });
+        let content = format!(
+            "Applied {} operations to {} files in a single transaction.\n\n{}",
+            count,
+            changes.len(),
+            messages.join("\n\n")
+        );
+        Ok(Outcome {
+            content,
+            changes,
+            path_changes: Vec::new(),
+        })
+    }
+}
 
-    Ok(format!(
-        "The file {} has been edited.\nHere's the result of running `cat -n` on a snippet:\n{}\nReview the changes and make sure they are as expected (correct indentation, no duplicate lines, etc). Edit the file again if necessary.",
-        path.display(), context
-    ))
+// Every command must be classified here, so new commands are covered by
+// read-only mode and edit history
+fn is_mutating(command: &Command) -> bool {
+    match command {
+        Command::View | Command::Search | Command::Find => false,
+        Command::Create
+        | Command::StrReplace
+        | Command::Insert
+        | Command::Delete
+        | Command::UndoEdit
+        | Command::MultiEdit
+        | Command::Transaction
+        | Command::ApplyPatch
+        | Command::Rename
+        | Command::Copy
+        | Command::Remove
+        | Command::Mkdir
+        | Command::ReplaceLines => true,
+    }
+}
+
+/// A change to a single file, computed by a command before anything is
+/// written
+#[derive(Debug, Clone)]
+pub struct FileChange {
+    pub path: PathBuf,
+    /// The contents before the change, or `None` if the file did not exist
+    pub before: Option<String>,
+    /// The contents after the change, or `None` if the file is removed
+    pub after: Option<String>,
+}
+
+/// What a command returns to the model, along with the file changes the
+/// editor still has to write
+#[derive(Debug)]
+pub struct Outcome {
+    pub content: String,
+    pub changes: Vec<FileChange>,
+    /// Changes to the filesystem that are not edits to a file's contents.
+    /// These are not recorded in the edit history.
+    pub path_changes: Vec<PathChange>,
 }
 
-pub fn create(path: &Path, content: &str) -> Result<String, EditorError> {
-    validate_path(path, &Command::Create)?;
+impl Outcome {
+    fn output(content: String) -> Self {
+        Self {
+            content,
+            changes: Vec::new(),
+            path_changes: Vec::new(),
+        }
+    }
 
-    // Create parent directories if they don't exist
-    if let Some(parent) = path.parent() {
-        if !parent.exists() {
-            fs::create_dir_all(parent)?;
+    fn edit(content: String, change: FileChange) -> Self {
+        Self {
+            content,
+            changes: vec![change],
+            path_changes: Vec::new(),
         }
     }
 
-    fs::write(path, content)?;
+    fn path_change(content: String, change: PathChange) -> Self {
+        Self {
+            content,
+            changes: Vec::new(),
+            path_changes: vec![change],
+        }
+    }
 
-    Ok(format!("File created successfully at: {}", path.display()))
+    fn is_empty(&self) -> bool {
+        self.changes.is_empty() && self.path_changes.is_empty()
+    }
 }
 
-// We'll remove the actual implementation since it's not used
-// The handle_command method already returns UndoNotImplemented error directly
-
-pub fn view(
-    path: &Path,
-    view_range: Option<&[i32]>,
-    max_depth: Option<usize>,
-) -> Result<String, EditorError> {
-    validate_path(path, &Command::View)?;
+/// A filesystem change that moves, copies or removes paths as they are,
+/// rather than rewriting a file's contents
+#[derive(Debug, Clone)]
+pub enum PathChange {
+    CreateDir(PathBuf),
+    Rename { from: PathBuf, to: PathBuf },
+    Copy { from: PathBuf, to: PathBuf },
+    Remove { path: PathBuf, recursive: bool },
+}
 
-    if path.is_dir() {
-        // Handle directory listing
-        if view_range.is_some() {
-            return Err(EditorError::ViewRangeForDirectory);
+impl PathChange {
+    fn paths(&self) -> Vec<&Path> {
+        match self {
+            PathChange::CreateDir(path) | PathChange::Remove { path, .. } => vec![path],
+            PathChange::Rename { from, to } | PathChange::Copy { from, to } => vec![from, to],
         }
+    }
 
-        let mut files = Vec::new();
-        let depth = max_depth.unwrap_or(1);
-        list_files_recursive(path, &mut files, 0, depth)?;
-        files.sort();
+    fn apply(&self) -> std::io::Result<()> {
+        match self {
+            PathChange::CreateDir(path) => fs::create_dir_all(path),
+            PathChange::Rename { from, to } => {
+                create_parent_dirs(to)?;
+                fs::rename(from, to)
+            }
+            PathChange::Copy { from, to } => {
+                create_parent_dirs(to)?;
+                fs::copy(from, to).map(|_| ())
+            }
+            PathChange::Remove { path, recursive } => {
+                if !path.is_dir() {
+                    fs::remove_file(path)
+                } else if *recursive {
+                    fs::remove_dir_all(path)
+                } else {
+                    fs::remove_dir(path)
+                }
+            }
+        }
+    }
+}
 
-        Ok(files.join("\n"))
-    } else {
-        // Handle file content view
-        let content = fs::read_to_string(path)?;
-        let lines: Vec<_> = content.lines().collect();
+fn create_parent_dirs(path: &Path) -> std::io::Result<()> {
+    match path.parent() {
+        Some(parent) if !parent.exists() => fs::create_dir_all(parent),
+        _ => Ok(()),
+    }
+}
 
-        if let Some(range) = view_range {
-            if range.len() != 2 {
-                return Err(EditorError::InvalidRange(
-                    "view_range must be an array with exactly 2 elements: [start_line, end_line]"
-                        .to_string(),
-                ));
+fn apply_change(change: &FileChange) -> std::io::Result<()> {
+    match &change.after {
+        Some(content) => {
+            create_parent_dirs(&change.path)?;
+            write_atomic(&change.path, content)
+        }
+        None => {
+            if change.path.exists() {
+                fs::remove_file(&change.path)?;
             }
+            Ok(())
+        }
+    }
+}
 
-            let start = range[0];
-            let end = range[1];
+// Write every change, restoring the files already written if one of them
+// fails, so a request touching several files is never left half applied
+fn commit(changes: &[FileChange]) -> Result<(), EditorError> {
+    for (i, change) in changes.iter().enumerate() {
+        if let Err(e) = apply_change(change) {
+            if changes.len() == 1 {
+                return Err(e.into());
+            }
+            for written in changes[..i].iter().rev() {
+                let _ = apply_change(&FileChange {
+                    path: written.path.clone(),
+                    before: written.after.clone(),
+                    after: written.before.clone(),
+                });
+            }
+            return Err(EditorError::CommitFailed(change.path.clone(), e));
+        }
+    }
+    Ok(())
+}
+
+/// The filesystem as a command sees it: file contents on disk, overlaid with
+/// the changes staged by earlier operations of the same transaction
+#[derive(Debug, Default)]
+pub struct Overlay {
+    staged: HashMap<PathBuf, Option<String>>,
+}
+
+impl Overlay {
+    fn stage(&mut self, change: &FileChange) {
+        self.staged
+            .insert(change.path.clone(), change.after.clone());
+    }
+
+    fn read(&self, path: &Path) -> std::io::Result<String> {
+        match self.staged.get(path) {
+            Some(Some(content)) => Ok(content.clone()),
+            Some(None) => Err(std::io::ErrorKind::NotFound.into()),
+            None => fs::read_to_string(path),
+        }
+    }
+
+    // Like `validate_path`, for a file that may only exist once staged
+    // changes are written
+    fn validate(&self, path: &Path, command: &Command) -> Result<(), EditorError> {
+        if !path.is_absolute() {
+            return Err(EditorError::NotAbsolutePath(path.to_path_buf()));
+        }
+
+        match (self.staged.get(path), command) {
+            (Some(Some(_)), Command::Create) => {
+                Err(EditorError::FileAlreadyExists(path.to_path_buf()))
+            }
+            (Some(None), Command::Create) | (Some(Some(_)), _) => Ok(()),
+            (Some(None), _) => Err(EditorError::PathNotFound(path.to_path_buf())),
+            (None, _) => validate_path(path, command),
+        }
+    }
+}
+
+const DEFAULT_DIFF_CONTEXT: usize = 3;
+
+// Render a change as a unified diff, using /dev/null for a missing side
+fn unified_diff(change: &FileChange, context: usize) -> String {
+    let path = change.path.display().to_string();
+    let old_header = if change.before.is_some() {
+        path.as_str()
+    } else {
+        "/dev/null"
+    };
+    let new_header = if change.after.is_some() {
+        path.as_str()
+    } else {
+        "/dev/null"
+    };
+
+    similar::TextDiff::from_lines(
+        change.before.as_deref().unwrap_or_default(),
+        change.after.as_deref().unwrap_or_default(),
+    )
+    .unified_diff()
+    .context_radius(context)
+    .header(old_header, new_header)
+    .to_string()
+}
+
+fn dispatch(
+    files: &Overlay,
+    input: Input,
+    history: Option<&History>,
+    root: Option<&Path>,
+    snippet_lines: usize,
+) -> Result<Outcome, EditorError> {
+    let path = PathBuf::from(&input.path);
+    let max_locations = input.max_locations.unwrap_or(MAX_LOCATIONS);
+
+    match input.command {
+        Command::View => view(
+            &path,
+            input.view_range.as_deref(),
+            input.max_depth,
+            input.max_characters,
+            input.format.unwrap_or_default(),
+            input.max_entries.unwrap_or(MAX_ENTRIES),
+        )
+        .map(Outcome::output),
+        Command::Search => {
+            let pattern = input
+                .pattern
+                .ok_or(EditorError::MissingPattern(Command::Search))?;
+            search(
+                &path,
+                &pattern,
+                input.include.as_deref(),
+                input.exclude.as_deref(),
+                input.context_lines.unwrap_or(0),
+                input.max_results.unwrap_or(MAX_RESULTS),
+            )
+            .map(Outcome::output)
+        }
+        Command::Find => {
+            let pattern = input
+                .pattern
+                .ok_or(EditorError::MissingPattern(Command::Find))?;
+            find(
+                &path,
+                &pattern,
+                input.sort_by.unwrap_or_default(),
+                input.max_results.unwrap_or(MAX_RESULTS),
+            )
+            .map(Outcome::output)
+        }
+        Command::Create => {
+            let file_text = input.file_text.ok_or(EditorError::MissingFileText)?;
+            create(files, &path, &file_text)
+        }
+        Command::StrReplace => {
+            let edit = EditOperation {
+                old_str: input.old_str.ok_or(EditorError::MissingOldStr)?,
+                new_str: input.new_str,
+                allow_multi: input.allow_multi,
+                use_regex: input.use_regex,
+                match_mode: input.match_mode,
+                occurrence: input.occurrence,
+                line_range: input.line_range,
+            };
+            str_replace(files, &path, &edit, max_locations, snippet_lines)
+        }
+        Command::Insert => {
+            let insert_line = input.insert_line.ok_or(EditorError::MissingInsertLine)?;
+            let new_str = input
+                .new_str
+                .ok_or(EditorError::MissingNewStr(Command::Insert))?;
+            insert(files, &path, insert_line, &new_str, snippet_lines)
+        }
+        Command::Delete => {
+            let delete_range = input.delete_range.ok_or(EditorError::MissingDeleteRange)?;
+            delete(files, &path, &delete_range, snippet_lines)
+        }
+        Command::UndoEdit => undo_edit(&path, history),
+        Command::MultiEdit => {
+            let edits = input
+                .edits
+                .filter(|edits| !edits.is_empty())
+                .ok_or(EditorError::MissingEdits)?;
+            multi_edit(files, &path, &edits, max_locations, snippet_lines)
+        }
+        Command::ApplyPatch => {
+            let patch = input.patch.ok_or(EditorError::MissingPatch)?;
+            let tolerance = Tolerance {
+                fuzz: input.fuzz.unwrap_or(0),
+                max_offset: input.max_offset,
+            };
+            apply_patch(files, &path, &patch, tolerance, root, snippet_lines)
+        }
+        Command::Rename | Command::Copy => {
+            let new_path = input
+                .new_path
+                .ok_or_else(|| EditorError::MissingNewPath(input.command.clone()))?;
+            let new_path = PathBuf::from(new_path);
+            if input.command == Command::Rename {
+                rename(&path, &new_path)
+            } else {
+                copy(&path, &new_path)
+            }
+        }
+        Command::Remove => remove(&path, input.recursive.unwrap_or(false)),
+        Command::Mkdir => mkdir(&path),
+        Command::ReplaceLines => {
+            let line_range = input.line_range.ok_or(EditorError::MissingLineRange)?;
+            let new_str = input
+                .new_str
+                .ok_or(EditorError::MissingNewStr(Command::ReplaceLines))?;
+            replace_lines(files, &path, &line_range, &new_str, snippet_lines)
+        }
+        // Transactions are staged by the editor, one operation at a time
+        Command::Transaction => unreachable!("transaction is handled by Editor"),
+    }
+}
+
+pub fn insert(
+    files: &Overlay,
+    path: &Path,
+    insert_line: i32,
+    new_str: &str,
+    snippet_lines: usize,
+) -> Result<Outcome, EditorError> {
+    files.validate(path, &Command::Insert)?;
+
+    // Path validation already handles directories
+
+    let content = files.read(path)?;
+    let format = LineFormat::detect(&content);
+    let lines = split_lines(&content);
+
+    if insert_line < 0 || insert_line > lines.len() as i32 {
+        return Err(EditorError::InvalidRange(format!(
+            "Invalid `insert_line` parameter: {}. It should be within the range of lines of the file: [0, {}]",
+            insert_line,
+            lines.len()
+        )));
+    }
+
+    let inserted = format.new_lines(new_str);
+    let region = insert_line as usize..insert_line as usize + inserted.len();
+    let mut new_lines = lines.clone();
+    new_lines.splice(region.start..region.start, inserted);
+    let new_content = format.join(&new_lines);
+
+    // Show the inserted lines with context on either side
+    let context = format_snippets(&new_content, Some(region), snippet_lines).join("\n");
+
+    let message = format!(
+        "The file {} has been edited.\nHere's the result of running `cat -n` on a snippet:\n{}\nReview the changes and make sure they are as expected (correct indentation, no duplicate lines, etc). Edit the file again if necessary.",
+        path.display(), context
+    );
+    Ok(Outcome::edit(
+        message,
+        FileChange {
+            path: path.to_path_buf(),
+            before: Some(content),
+            after: Some(new_content),
+        },
+    ))
+}
+
+pub fn replace_lines(
+    files: &Overlay,
+    path: &Path,
+    line_range: &[i32],
+    new_str: &str,
+    snippet_lines: usize,
+) -> Result<Outcome, EditorError> {
+    files.validate(path, &Command::ReplaceLines)?;
+
+    let content = files.read(path)?;
+    let format = LineFormat::detect(&content);
+    let lines = split_lines(&content);
+
+    let range = validate_line_range("line_range", line_range, lines.len())?;
+    let replaced = format.new_lines(new_str);
+    let region = range.start..range.start + replaced.len();
+    let mut new_lines = lines.clone();
+    new_lines.splice(range.clone(), replaced);
+    let new_content = format.join(&new_lines);
+
+    // Show the new lines with context on either side
+    let context = format_snippets(&new_content, Some(region), snippet_lines).join("\n");
+
+    let message = format!(
+        "The file {} has been edited. Replaced lines {}-{}.\nHere's the result of running `cat -n` on a snippet:\n{}\nReview the changes and make sure they are as expected (correct indentation, no duplicate lines, etc). Edit the file again if necessary.",
+        path.display(),
+        range.start + 1,
+        range.end,
+        context
+    );
+    Ok(Outcome::edit(
+        message,
+        FileChange {
+            path: path.to_path_buf(),
+            before: Some(content),
+            after: Some(new_content),
+        },
+    ))
+}
+
+pub fn create(files: &Overlay, path: &Path, content: &str) -> Result<Outcome, EditorError> {
+    files.validate(path, &Command::Create)?;
+
+    Ok(Outcome::edit(
+        format!("File created successfully at: {}", path.display()),
+        FileChange {
+            path: path.to_path_buf(),
+            before: None,
+            after: Some(content.to_string()),
+        },
+    ))
+}
+
+pub fn rename(path: &Path, new_path: &Path) -> Result<Outcome, EditorError> {
+    validate_path(path, &Command::Rename)?;
+    validate_destination(new_path)?;
+
+    Ok(Outcome::path_change(
+        format!("Renamed {} to {}.", path.display(), new_path.display()),
+        PathChange::Rename {
+            from: path.to_path_buf(),
+            to: new_path.to_path_buf(),
+        },
+    ))
+}
+
+pub fn copy(path: &Path, new_path: &Path) -> Result<Outcome, EditorError> {
+    validate_path(path, &Command::Copy)?;
+    validate_destination(new_path)?;
+
+    Ok(Outcome::path_change(
+        format!("Copied {} to {}.", path.display(), new_path.display()),
+        PathChange::Copy {
+            from: path.to_path_buf(),
+            to: new_path.to_path_buf(),
+        },
+    ))
+}
+
+// The target of a rename or copy, which must not exist yet
+fn validate_destination(path: &Path) -> Result<(), EditorError> {
+    if !path.is_absolute() {
+        return Err(EditorError::NotAbsolutePath(path.to_path_buf()));
+    }
+    if path.exists() {
+        return Err(EditorError::PathAlreadyExists(path.to_path_buf()));
+    }
+    Ok(())
+}
+
+pub fn remove(path: &Path, recursive: bool) -> Result<Outcome, EditorError> {
+    validate_path(path, &Command::Remove)?;
+
+    if path.is_dir() {
+        if !recursive && fs::read_dir(path)?.next().is_some() {
+            return Err(EditorError::DirectoryNotEmpty(path.to_path_buf()));
+        }
+        return Ok(Outcome::path_change(
+            format!("Removed the directory {}.", path.display()),
+            PathChange::Remove {
+                path: path.to_path_buf(),
+                recursive,
+            },
+        ));
+    }
+
+    let message = format!("Removed the file {}.", path.display());
+    // Removing a text file is an ordinary edit, so it can be undone
+    match fs::read_to_string(path) {
+        Ok(content) => Ok(Outcome::edit(
+            message,
+            FileChange {
+                path: path.to_path_buf(),
+                before: Some(content),
+                after: None,
+            },
+        )),
+        Err(_) => Ok(Outcome::path_change(
+            message,
+            PathChange::Remove {
+                path: path.to_path_buf(),
+                recursive: false,
+            },
+        )),
+    }
+}
+
+pub fn mkdir(path: &Path) -> Result<Outcome, EditorError> {
+    validate_path(path, &Command::Mkdir)?;
+
+    Ok(Outcome::path_change(
+        format!("Created the directory {}.", path.display()),
+        PathChange::CreateDir(path.to_path_buf()),
+    ))
+}
+
+pub fn undo_edit(path: &Path, history: Option<&History>) -> Result<Outcome, EditorError> {
+    let history = history.ok_or(EditorError::HistoryDisabled)?;
+
+    if !path.is_absolute() {
+        return Err(EditorError::NotAbsolutePath(path.to_path_buf()));
+    }
+
+    // The entry is only dropped once the file has been restored
+    let previous = history
+        .peek(path)?
+        .ok_or_else(|| EditorError::NoEditHistory(path.to_path_buf()))?;
+
+    let message = match &previous {
+        // The last edit created the file
+        None => format!(
+            "Last edit to {} undone successfully. The file has been removed.",
+            path.display()
+        ),
+        Some(content) => {
+            let lines: Vec<_> = content.lines().collect();
+            format!(
+                "Last edit to {} undone successfully. Here's the result of running `cat -n` on {}:\n{}\n",
+                path.display(),
+                path.display(),
+                format_with_line_numbers(&lines, 1)
+            )
+        }
+    };
+
+    Ok(Outcome::edit(
+        message,
+        FileChange {
+            path: path.to_path_buf(),
+            before: fs::read_to_string(path).ok(),
+            after: previous,
+        },
+    ))
+}
+
+pub fn view(
+    path: &Path,
+    view_range: Option<&[i32]>,
+    max_depth: Option<usize>,
+    max_characters: Option<usize>,
+    format: ListFormat,
+    max_entries: usize,
+) -> Result<String, EditorError> {
+    validate_path(path, &Command::View)?;
+
+    if path.is_dir() {
+        // Handle directory listing
+        if view_range.is_some() {
+            return Err(EditorError::ViewRangeForDirectory);
+        }
+
+        let max_depth = max_depth.unwrap_or(1);
+        let mut entries: Vec<ignore::DirEntry> =
+            walk_dir(path, Some(max_depth), Override::empty()).collect();
+        // Paths sort component by component, so a directory's entries
+        // directly follow it
+        entries.sort_by(|a, b| a.path().cmp(b.path()));
+
+        let omitted = entries.len().saturating_sub(max_entries);
+        entries.truncate(max_entries);
+        let mut listing: Vec<String> = match format {
+            ListFormat::Paths => entries
+                .iter()
+                .map(|entry| entry.path().to_string_lossy().to_string())
+                .collect(),
+            ListFormat::Long => entries.iter().map(describe_entry).collect(),
+            ListFormat::Tree => std::iter::once(format!("{}/", path.display()))
+                .chain(
+                    entries
+                        .iter()
+                        .map(|entry| tree_line(path, entry, max_depth)),
+                )
+                .collect(),
+        };
+        if omitted > 0 {
+            listing.push(format!("{} more entries omitted", omitted));
+        }
+
+        Ok(listing.join("\n"))
+    } else {
+        // Handle file content view
+        let content = fs::read_to_string(path)?;
+        let lines: Vec<_> = content.lines().collect();
+
+        if let Some(range) = view_range {
+            if range.len() != 2 {
+                return Err(EditorError::InvalidRange(
+                    "view_range must be an array with exactly 2 elements: [start_line, end_line]"
+                        .to_string(),
+                ));
+            }
+
+            let start = range[0];
+            let end = range[1];
 
             // Adjust negative indices
             let adjusted_start = if start < 0 {
@@ -348,29 +1528,215 @@ pub fn view(
 
             // Format with line numbers, starting at the adjusted_start + 1 (to match 1-based line numbering)
             let init_line = adjusted_start + 1;
-            let formatted_lines = format_with_line_numbers(
-                &lines[adjusted_start as usize..end_idx],
-                init_line as usize,
+            let formatted_lines = maybe_truncate(
+                format_with_line_numbers(
+                    &lines[adjusted_start as usize..end_idx],
+                    init_line as usize,
+                ),
+                max_characters,
             );
 
             Ok(format!(
-                "Here's the result of running `cat -n` on {}:\n{}\n",
+                "Here's the result of running `cat -n` on {}:\n{}\nContent hash: {}\n",
                 path.display(),
-                formatted_lines
+                formatted_lines,
+                content_hash(&content)
             ))
         } else {
             // Format the whole file with line numbers
-            let formatted_lines = format_with_line_numbers(&lines, 1);
+            let formatted_lines =
+                maybe_truncate(format_with_line_numbers(&lines, 1), max_characters);
 
             Ok(format!(
-                "Here's the result of running `cat -n` on {}:\n{}\n",
+                "Here's the result of running `cat -n` on {}:\n{}\nContent hash: {}\n",
                 path.display(),
-                formatted_lines
+                formatted_lines,
+                content_hash(&content)
             ))
         }
     }
 }
 
+const MAX_RESULTS: usize = 100;
+
+/// Searches a file, or every file below a directory, for lines matching a
+/// regex. Directories are walked with the same rules as directory `view`,
+/// and `include`/`exclude` globs (relative to `path`) narrow the files
+/// searched.
+pub fn search(
+    path: &Path,
+    pattern: &str,
+    include: Option<&[String]>,
+    exclude: Option<&[String]>,
+    context: usize,
+    max_results: usize,
+) -> Result<String, EditorError> {
+    validate_path(path, &Command::Search)?;
+
+    let re = Regex::new(pattern).map_err(|e| EditorError::InvalidRegex(e.to_string()))?;
+
+    let mut files: Vec<PathBuf> = if path.is_dir() {
+        walk_dir(path, None, glob_filter(path, include, exclude)?)
+            .filter(|entry| entry.file_type().is_some_and(|t| t.is_file()))
+            .map(ignore::DirEntry::into_path)
+            .collect()
+    } else {
+        vec![path.to_path_buf()]
+    };
+    files.sort();
+
+    let mut results = String::new();
+    let mut count = 0;
+    let mut matched_files = 0;
+    let mut capped = false;
+    for file in &files {
+        // Binary and unreadable files are skipped
+        let Ok(content) = fs::read_to_string(file) else {
+            continue;
+        };
+        let lines: Vec<_> = content.lines().collect();
+        let mut matches: Vec<usize> = (0..lines.len())
+            .filter(|&i| re.is_match(lines[i]))
+            .collect();
+        if matches.is_empty() {
+            continue;
+        }
+        // Earlier files used up every result
+        if count == max_results {
+            capped = true;
+            break;
+        }
+        if count + matches.len() > max_results {
+            matches.truncate(max_results - count);
+            capped = true;
+        }
+        count += matches.len();
+        matched_files += 1;
+
+        for block in match_blocks(&matches, context, lines.len()) {
+            let numbers: Vec<_> = matches
+                .iter()
+                .filter(|&&i| block.contains(&i))
+                .map(|i| (i + 1).to_string())
+                .collect();
+            let _ = writeln!(
+                results,
+                "{}:{}\n{}",
+                file.display(),
+                numbers.join(","),
+                format_with_line_numbers(&lines[block.clone()], block.start + 1)
+            );
+        }
+        if capped {
+            break;
+        }
+    }
+
+    if count == 0 {
+        return Ok(format!(
+            "No matches found for `{}` in {}.",
+            pattern,
+            path.display()
+        ));
+    }
+
+    let mut message = format!(
+        "Found {} matches for `{}` in {} files:\n{}",
+        count, pattern, matched_files, results
+    );
+    if capped {
+        let _ = write!(
+            message,
+            "Results were capped at {} matches. Narrow the search with a more specific pattern, path or `include` globs to see the rest.",
+            max_results
+        );
+    }
+    Ok(message.trim_end().to_string())
+}
+
+/// Finds the files below a directory whose paths, relative to it, match a
+/// glob. Directories are walked with the same rules as directory `view`.
+pub fn find(
+    path: &Path,
+    pattern: &str,
+    sort_by: SortOrder,
+    max_results: usize,
+) -> Result<String, EditorError> {
+    validate_path(path, &Command::Find)?;
+    if !path.is_dir() {
+        return Err(EditorError::NotADirectory(
+            path.to_path_buf(),
+            Command::Find,
+        ));
+    }
+
+    let glob = [pattern.to_string()];
+    let mut files: Vec<(PathBuf, Option<std::time::SystemTime>)> =
+        walk_dir(path, None, glob_filter(path, Some(&glob), None)?)
+            .filter(|entry| entry.file_type().is_some_and(|t| t.is_file()))
+            .map(|entry| {
+                let modified = entry.metadata().ok().and_then(|m| m.modified().ok());
+                (entry.into_path(), modified)
+            })
+            .collect();
+
+    if files.is_empty() {
+        return Ok(format!(
+            "No files found matching `{}` in {}.",
+            pattern,
+            path.display()
+        ));
+    }
+
+    match sort_by {
+        // Ties, and files without a modification time, fall back to the path
+        SortOrder::Modified => files.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0))),
+        SortOrder::Name => files.sort(),
+    }
+
+    let total = files.len();
+    files.truncate(max_results);
+    let mut message = format!(
+        "Found {} files matching `{}`:\n{}",
+        total,
+        pattern,
+        files
+            .iter()
+            .map(|(file, _)| file.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("\n")
+    );
+    if total > max_results {
+        let _ = write!(message, "\n{} more files omitted", total - max_results);
+    }
+    Ok(message)
+}
+
+// Group matching lines, with `context` lines around each, into the blocks of
+// lines to show, merging blocks that touch or overlap
+fn match_blocks(matches: &[usize], context: usize, line_count: usize) -> Vec<Range<usize>> {
+    let mut blocks: Vec<Range<usize>> = Vec::new();
+    for &line in matches {
+        let start = line.saturating_sub(context);
+        let end = (line + context + 1).min(line_count);
+        match blocks.last_mut() {
+            Some(last) if start <= last.end => last.end = end,
+            _ => blocks.push(start..end),
+        }
+    }
+    blocks
+}
+
+/// A short hash of a file's contents, returned by `view` so later edits can
+/// pass it as `expected_hash` to detect changes made in between.
+pub fn content_hash(content: &str) -> String {
+    Sha256::digest(content.as_bytes())
+        .iter()
+        .take(8)
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
 // Helper function to format lines with line numbers
 fn format_with_line_numbers(lines: &[&str], init_line: usize) -> String {
     lines
@@ -381,145 +1747,1099 @@ fn format_with_line_numbers(lines: &[&str], init_line: usize) -> String {
         .join("\n")
 }
 
-fn list_files_recursive(
-    dir: &Path,
-    files: &mut Vec<String>,
-    depth: usize,
-    max_depth: usize,
-) -> std::io::Result<()> {
-    if depth > max_depth {
-        return Ok(());
-    }
-
-    for entry in fs::read_dir(dir)? {
-        let entry = entry?;
-        let path = entry.path();
-
-        // Skip hidden files
-        if path
-            .file_name()
-            .and_then(|name| name.to_str())
-            .map_or(false, |name| name.starts_with('.'))
-        {
-            continue;
-        }
+const SNIPPET_LINES: usize = 4;
 
-        files.push(path.to_string_lossy().to_string());
+// Snippets of every edited region, followed by the usual review reminder
+fn snippets_message(
+    path: &Path,
+    content: &str,
+    regions: impl IntoIterator<Item = Range<usize>>,
+    context: usize,
+) -> String {
+    let mut message = String::new();
+    for snippet in format_snippets(content, regions, context) {
+        let _ = writeln!(
+            message,
+            "Here's the result of running `cat -n` on a snippet of {}:\n{}",
+            path.display(),
+            snippet
+        );
+    }
+    message.push_str(
+        "Review the changes and make sure they are as expected. Edit the file again if necessary.",
+    );
+    message
+}
 
-        if path.is_dir() && depth < max_depth {
-            list_files_recursive(&path, files, depth + 1, max_depth)?;
+// The line ranges of `after` that differ from `before`
+fn changed_regions(before: &str, after: &str) -> Vec<Range<usize>> {
+    similar::TextDiff::from_lines(before, after)
+        .ops()
+        .iter()
+        .filter(|op| op.tag() != similar::DiffTag::Equal)
+        .map(|op| op.new_range())
+        .collect()
+}
+
+// Format `cat -n` snippets of `content` around each edited region, given in
+// order as ranges of line indices. Regions whose context overlaps are shown
+// as a single snippet.
+fn format_snippets(
+    content: &str,
+    regions: impl IntoIterator<Item = Range<usize>>,
+    context: usize,
+) -> Vec<String> {
+    let lines: Vec<_> = content.lines().collect();
+    let mut merged: Vec<Range<usize>> = Vec::new();
+
+    for region in regions {
+        let end = (region.end + context).min(lines.len());
+        let start = region.start.saturating_sub(context).min(end);
+        match merged.last_mut() {
+            Some(last) if start <= last.end => last.end = last.end.max(end),
+            _ => merged.push(start..end),
         }
     }
 
+    merged
+        .into_iter()
+        .map(|range| format_with_line_numbers(&lines[range.clone()], range.start + 1))
+        .collect()
+}
+
+/// Writes `content` to `path` without ever leaving it partially written.
+///
+/// The content goes to a temporary file in the same directory, which is
+/// synced and then renamed over the target. An existing file's permissions
+/// are carried over, and a symlinked target is written through rather than
+/// replaced.
+fn write_atomic(path: &Path, content: &str) -> std::io::Result<()> {
+    let target = match path.canonicalize() {
+        Ok(target) => target,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => path.to_path_buf(),
+        Err(e) => return Err(e),
+    };
+    let dir = target.parent().unwrap_or(Path::new("/"));
+
+    let mut builder = tempfile::Builder::new();
+    builder.prefix(".tmp-edit-");
+    #[cfg(unix)]
+    if !target.exists() {
+        use std::os::unix::fs::PermissionsExt;
+        // Subject to the umask, matching a file created with `fs::write`
+        builder.permissions(fs::Permissions::from_mode(0o666));
+    }
+
+    let mut file = builder.tempfile_in(dir)?;
+    file.write_all(content.as_bytes())?;
+    if let Ok(metadata) = fs::metadata(&target) {
+        file.as_file().set_permissions(metadata.permissions())?;
+    }
+    file.as_file().sync_all()?;
+    file.persist(&target).map_err(|e| e.error)?;
+
+    // Make the rename itself durable
+    #[cfg(unix)]
+    fs::File::open(dir)?.sync_all()?;
+
     Ok(())
 }
 
+const TRUNCATED_MESSAGE: &str = "<response clipped><NOTE>To save on context only part of this file has been shown to you. You should retry this tool after you have searched inside the file with `grep -n` in order to find the line numbers of what you are looking for.</NOTE>";
+
+// Helper function to clip output to at most `max_characters` characters
+fn maybe_truncate(content: String, max_characters: Option<usize>) -> String {
+    match max_characters {
+        Some(max) if content.chars().count() > max => {
+            let clipped: String = content.chars().take(max).collect();
+            format!("{}{}", clipped, TRUNCATED_MESSAGE)
+        }
+        _ => content,
+    }
+}
+
+/// Walks the entries below `dir`, down to `max_depth` levels below its
+/// children, skipping hidden files and anything ignored by `.gitignore`,
+/// `.ignore` or git's global excludes. The ignore files apply even outside of
+/// a git repository.
+/// Only entries allowed by `globs` are returned.
+fn walk_dir(
+    dir: &Path,
+    max_depth: Option<usize>,
+    globs: Override,
+) -> impl Iterator<Item = ignore::DirEntry> {
+    ignore::WalkBuilder::new(dir)
+        .max_depth(max_depth.map(|depth| depth + 1))
+        .require_git(false)
+        .overrides(globs)
+        .build()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.depth() > 0)
+}
+
+// Globs relative to `dir` that limit a walk to the files matching any of
+// `include`, minus those matching any of `exclude`
+fn glob_filter(
+    dir: &Path,
+    include: Option<&[String]>,
+    exclude: Option<&[String]>,
+) -> Result<Override, EditorError> {
+    let mut builder = OverrideBuilder::new(dir);
+    for glob in include.unwrap_or_default() {
+        builder
+            .add(glob)
+            .map_err(|e| EditorError::InvalidGlob(e.to_string()))?;
+    }
+    for glob in exclude.unwrap_or_default() {
+        builder
+            .add(&format!("!{}", glob))
+            .map_err(|e| EditorError::InvalidGlob(e.to_string()))?;
+    }
+    builder
+        .build()
+        .map_err(|e| EditorError::InvalidGlob(e.to_string()))
+}
+
+const MAX_ENTRIES: usize = 1000;
+
+// An entry of a tree listing: its name, indented two spaces per level below
+// `root`. Directories whose contents are past `max_depth` show how many
+// entries they hold.
+fn tree_line(root: &Path, entry: &ignore::DirEntry, max_depth: usize) -> String {
+    let path = entry.path();
+    let depth = path
+        .strip_prefix(root)
+        .map_or(1, |p| p.components().count());
+    let indent = "  ".repeat(depth.saturating_sub(1));
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+
+    // Symlinks are not followed, since they may lead out of the root
+    match entry.file_type() {
+        Some(t) if t.is_symlink() => return format!("{}{}@", indent, name),
+        Some(t) if t.is_dir() => {}
+        _ => return format!("{}{}", indent, name),
+    }
+    let count = if depth > max_depth {
+        walk_dir(path, Some(0), Override::empty()).count()
+    } else {
+        0
+    };
+    if count > 0 {
+        format!("{}{}/ ({} entries)", indent, name, count)
+    } else {
+        format!("{}{}/", indent, name)
+    }
+}
+
+// A directory entry for the long listing format: directories end with `/`,
+// symlinks with `@`, and regular files show their size and, for text files,
+// their line count. Symlinks are not followed and other kinds of files (such
+// as named pipes) are never opened.
+fn describe_entry(entry: &ignore::DirEntry) -> String {
+    let path = entry.path();
+    let display = path.to_string_lossy();
+    match entry.file_type() {
+        Some(t) if t.is_dir() => return format!("{}/", display),
+        Some(t) if t.is_symlink() => return format!("{}@", display),
+        Some(t) if t.is_file() => {}
+        _ => return display.to_string(),
+    }
+
+    let Ok(metadata) = entry.metadata() else {
+        return display.to_string();
+    };
+    match count_lines(path) {
+        Some(lines) => format!("{} ({} bytes, {} lines)", display, metadata.len(), lines),
+        None => format!("{} ({} bytes)", display, metadata.len()),
+    }
+}
+
+// Count the lines of a text file a block at a time, so that large files are
+// not read into memory. Returns `None` for files that are not UTF-8.
+fn count_lines(path: &Path) -> Option<usize> {
+    let mut file = fs::File::open(path).ok()?;
+    let mut buf = [0u8; 8192];
+    // Bytes of a character split across blocks, carried to the next one
+    let mut carried = 0;
+    let mut lines = 0;
+    let mut last = None;
+
+    loop {
+        let read = file.read(&mut buf[carried..]).ok()?;
+        if read == 0 {
+            break;
+        }
+        let filled = carried + read;
+        let valid = match std::str::from_utf8(&buf[..filled]) {
+            Ok(_) => filled,
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(_) => return None,
+        };
+        lines += buf[..valid].iter().filter(|&&b| b == b'\n').count();
+        if valid > 0 {
+            last = Some(buf[valid - 1]);
+        }
+        buf.copy_within(valid..filled, 0);
+        carried = filled - valid;
+    }
+
+    if carried > 0 {
+        return None;
+    }
+    // Like `str::lines`, a final line without a newline still counts
+    Some(lines + usize::from(last.is_some_and(|b| b != b'\n')))
+}
+
 pub fn str_replace(
+    files: &Overlay,
     path: &Path,
-    old_str: &str,
-    new_str: &str,
-    allow_multi: bool,
-    use_regex: bool,
-) -> Result<String, EditorError> {
-    validate_path(path, &Command::StrReplace)?;
+    edit: &EditOperation,
+    max_locations: usize,
+    snippet_lines: usize,
+) -> Result<Outcome, EditorError> {
+    files.validate(path, &Command::StrReplace)?;
 
-    let content = fs::read_to_string(path)?;
+    let content = files.read(path)?;
+    let replacement = replace_in(&content, edit, max_locations)?;
 
-    let (new_content, count) = if use_regex {
-        // Regex-based replacement
-        let re = Regex::new(old_str)
-            .map_err(|e| EditorError::InvalidRegex(format!("Invalid regex pattern: {}", e)))?;
+    let mut message = format!(
+        "The file {} has been edited. Replaced {} occurrences of '{}'.\n",
+        path.display(),
+        replacement.regions.len(),
+        edit.old_str
+    );
+    let starts: Vec<_> = replacement
+        .regions
+        .iter()
+        .map(|region| (region.start + 1).to_string())
+        .collect();
+    if let [start] = starts.as_slice() {
+        let _ = writeln!(message, "The replacement starts at line {}.", start);
+    } else {
+        let _ = writeln!(
+            message,
+            "The replacements start at lines {}.",
+            starts.join(", ")
+        );
+    }
+    if replacement.matched_with != MatchMode::Exact {
+        let _ = writeln!(
+            message,
+            "The string was not found exactly, so it was matched {}.",
+            describe_match_mode(replacement.matched_with)
+        );
+    }
+    message.push_str(&snippets_message(
+        path,
+        &replacement.content,
+        replacement.regions.iter().cloned(),
+        snippet_lines,
+    ));
+
+    Ok(Outcome::edit(
+        message,
+        FileChange {
+            path: path.to_path_buf(),
+            before: Some(content),
+            after: Some(replacement.content),
+        },
+    ))
+}
 
-        if !allow_multi {
-            // Check for multiple matches first
-            let matches: Vec<_> = re.find_iter(&content).collect();
-            if matches.len() > 1 {
-                return Err(EditorError::StrReplace(
-                    format!("The regex pattern matches in multiple places ({} matches). Use `allow_multi: true` if you want to replace all occurrences.", matches.len())
-                ));
-            } else if matches.is_empty() {
-                return Err(EditorError::StrReplace(
-                    "The regex pattern does not match anywhere in the file.".to_string(),
-                ));
+/// Applies a unified diff. `path` is either the single file the patch
+/// changes, or a directory that relative paths in the patch are resolved
+/// against. Nothing is written unless every hunk applies.
+///
+/// Every file the patch names must lie inside `root`, which is checked before
+/// the file is read so that hunks cannot probe files outside of it.
+pub fn apply_patch(
+    files: &Overlay,
+    path: &Path,
+    patch: &str,
+    tolerance: Tolerance,
+    root: Option<&Path>,
+    snippet_lines: usize,
+) -> Result<Outcome, EditorError> {
+    if !path.is_absolute() {
+        return Err(EditorError::NotAbsolutePath(path.to_path_buf()));
+    }
+
+    let file_patches = patch::parse(patch).map_err(EditorError::InvalidPatch)?;
+    let base_dir = path.is_dir();
+    if !base_dir && file_patches.len() > 1 {
+        return Err(EditorError::InvalidPatch(format!(
+            "the patch changes {} files, but {} is a single file. Pass a directory as `path` to patch several files.",
+            file_patches.len(),
+            path.display()
+        )));
+    }
+    let resolve = |name: &str| {
+        if base_dir {
+            path.join(name)
+        } else {
+            path.to_path_buf()
+        }
+    };
+
+    let mut changes = Vec::new();
+    let mut report = String::new();
+    let mut rejected = false;
+    for file_patch in &file_patches {
+        let source = file_patch.old_path.map(resolve);
+        let target = file_patch.new_path.map(resolve);
+        if let Some(root) = root {
+            for path in source.iter().chain(&target) {
+                confine_path(path, root)?;
             }
         }
 
-        let new_content = re.replace_all(&content, new_str).to_string();
-        let count = re.find_iter(&content).count();
-        (new_content, count)
-    } else {
-        // Literal string replacement
-        if !content.contains(old_str) {
-            return Err(EditorError::StrReplace(
-                "The string was not found in the file.".to_string(),
-            ));
-        }
-
-        if !allow_multi {
-            // Count occurrences to check if there are multiple matches
-            let count = content.matches(old_str).count();
-            if count > 1 {
-                return Err(EditorError::StrReplace(
-                    format!("The string occurs in multiple places ({} occurrences). Use `allow_multi: true` if you want to replace all occurrences.", count)
-                ));
+        let before = match (&source, &target) {
+            (Some(source), target) => {
+                files.validate(source, &Command::ApplyPatch)?;
+                // Like `rename`, a patch that moves a file never overwrites
+                if let Some(target) = target.as_ref().filter(|target| *target != source) {
+                    files
+                        .validate(target, &Command::Create)
+                        .map_err(|e| match e {
+                            EditorError::FileAlreadyExists(path) => {
+                                EditorError::PathAlreadyExists(path)
+                            }
+                            e => e,
+                        })?;
+                }
+                Some(files.read(source)?)
             }
+            (None, Some(target)) => {
+                files.validate(target, &Command::Create)?;
+                None
+            }
+            (None, None) => {
+                return Err(EditorError::InvalidPatch(
+                    "a file cannot be /dev/null on both sides".to_string(),
+                ))
+            }
+        };
+
+        let (after, results) = patch::apply(
+            before.as_deref().unwrap_or_default(),
+            &file_patch.hunks,
+            tolerance,
+        );
+        let shown = target.as_deref().or(source.as_deref()).unwrap_or(path);
+        let _ = writeln!(report, "{}:", shown.display());
+        for (i, (hunk, result)) in file_patch.hunks.iter().zip(&results).enumerate() {
+            let _ = writeln!(
+                report,
+                "  hunk {} {}",
+                i + 1,
+                describe_hunk(hunk.header, result)
+            );
+            rejected |= matches!(result, HunkResult::Rejected { .. });
         }
 
-        let new_content = content.replace(old_str, new_str);
-        let count = content.matches(old_str).count();
-        (new_content, count)
-    };
+        match (source, target) {
+            (Some(source), Some(target)) if source != target => {
+                changes.push(FileChange {
+                    path: target,
+                    before: None,
+                    after: Some(after),
+                });
+                changes.push(FileChange {
+                    path: source,
+                    before,
+                    after: None,
+                });
+            }
+            (_, Some(target)) => changes.push(FileChange {
+                path: target,
+                before,
+                after: Some(after),
+            }),
+            (Some(source), None) => changes.push(FileChange {
+                path: source,
+                before,
+                after: None,
+            }),
+            (None, None) => unreachable!(),
+        }
+    }
+
+    if rejected {
+        return Err(EditorError::PatchRejected(report));
+    }
+
+    let mut message = format!("The patch has been applied.\n{}", report);
+    for change in &changes {
+        match (&change.before, &change.after) {
+            (_, None) => {
+                let _ = writeln!(
+                    message,
+                    "The file {} has been removed.",
+                    change.path.display()
+                );
+            }
+            (before, Some(after)) => {
+                let regions = changed_regions(before.as_deref().unwrap_or_default(), after);
+                for snippet in format_snippets(after, regions, snippet_lines) {
+                    let _ = writeln!(
+                        message,
+                        "Here's the result of running `cat -n` on a snippet of {}:\n{}",
+                        change.path.display(),
+                        snippet
+                    );
+                }
+            }
+        }
+    }
+    message.push_str(
+        "Review the changes and make sure they are as expected. Edit the file again if necessary.",
+    );
+
+    Ok(Outcome {
+        content: message,
+        changes,
+        path_changes: Vec::new(),
+    })
+}
+
+// One line of an apply_patch report
+fn describe_hunk(header: &str, result: &HunkResult) -> String {
+    match *result {
+        HunkResult::Applied { line, offset, fuzz } => {
+            let mut description = format!("{} applied at line {}", header, line);
+            if offset != 0 {
+                let _ = write!(description, " (offset {} lines)", offset);
+            }
+            if fuzz > 0 {
+                let _ = write!(description, " with fuzz {}", fuzz);
+            }
+            description
+        }
+        HunkResult::Rejected { line } => format!(
+            "{} rejected: its context and removed lines were not found near line {}",
+            header, line
+        ),
+    }
+}
 
-    fs::write(path, &new_content)?;
+pub fn multi_edit(
+    files: &Overlay,
+    path: &Path,
+    edits: &[EditOperation],
+    max_locations: usize,
+    snippet_lines: usize,
+) -> Result<Outcome, EditorError> {
+    files.validate(path, &Command::MultiEdit)?;
+
+    let content = files.read(path)?;
+
+    // Each edit applies to the result of the previous one, all in memory, so
+    // a failure part way through leaves the file untouched
+    let mut new_content = content.clone();
+    let mut count = 0;
+    for (index, edit) in edits.iter().enumerate() {
+        let replacement = replace_in(&new_content, edit, max_locations)
+            .map_err(|e| EditorError::MultiEdit(index, Box::new(e)))?;
+        count += replacement.regions.len();
+        new_content = replacement.content;
+    }
 
-    Ok(format!(
-        "The file {} has been edited. Replaced {} occurrences of '{}'.",
+    let mut message = format!(
+        "The file {} has been edited. Applied {} edits ({} replacements).\n",
         path.display(),
-        count,
-        old_str
+        edits.len(),
+        count
+    );
+    message.push_str(&snippets_message(
+        path,
+        &new_content,
+        changed_regions(&content, &new_content),
+        snippet_lines,
+    ));
+
+    Ok(Outcome::edit(
+        message,
+        FileChange {
+            path: path.to_path_buf(),
+            before: Some(content),
+            after: Some(new_content),
+        },
     ))
 }
 
-pub fn delete(path: &Path, delete_range: &[i32]) -> Result<String, EditorError> {
-    validate_path(path, &Command::Delete)?;
-
-    if delete_range.len() != 2 {
-        return Err(EditorError::InvalidRange(
-            "delete_range must be an array with exactly 2 elements: [start_line, end_line]"
+// Apply a single str_replace style edit to file contents
+fn replace_in(
+    content: &str,
+    edit: &EditOperation,
+    max_locations: usize,
+) -> Result<Replacement, EditorError> {
+    let allow_multi = edit.allow_multi.unwrap_or(false);
+    let use_regex = edit.use_regex.unwrap_or(false);
+    if edit.occurrence.is_some() && allow_multi {
+        return Err(EditorError::StrReplace(
+            "`occurrence` selects a single match and cannot be combined with `allow_multi: true`."
                 .to_string(),
         ));
     }
 
-    let content = fs::read_to_string(path)?;
-    let lines: Vec<_> = content.lines().collect();
+    // Match the file's line endings so a CRLF file stays CRLF
+    let format = LineFormat::detect(content);
+    let new_str = &*format.normalize(edit.new_str.as_deref().unwrap_or_default());
+    let old_str = &*format.normalize(&edit.old_str);
+
+    let lines = LineIndex::new(content);
+    let range = edit
+        .line_range
+        .as_deref()
+        .map(|range| validate_line_range("line_range", range, lines.count()))
+        .transpose()?;
+    let in_range = |span: &Range<usize>| {
+        range.as_ref().is_none_or(|range| {
+            range.contains(&lines.line_of(span.start))
+                && range.contains(&lines.line_of(span.end.saturating_sub(1).max(span.start)))
+        })
+    };
+
+    // Every match with its replacement, before narrowing them down
+    let mut matched_with = MatchMode::Exact;
+    let all: Vec<(Range<usize>, String)> = if use_regex {
+        let re = Regex::new(&edit.old_str).map_err(|e| EditorError::InvalidRegex(e.to_string()))?;
+        re.captures_iter(content)
+            .map(|caps| {
+                let mut expanded = String::new();
+                caps.expand(new_str, &mut expanded);
+                (caps.get(0).unwrap().range(), expanded)
+            })
+            .collect()
+    } else {
+        content
+            .match_indices(old_str)
+            .map(|(start, matched)| (start..start + matched.len(), new_str.to_string()))
+            .collect()
+    };
+    let mut matches: Vec<_> = all
+        .iter()
+        .filter(|(span, _)| in_range(span))
+        .cloned()
+        .collect();
+
+    // Fall back to a relaxed comparison only when there is no exact match
+    let match_mode = edit.match_mode.unwrap_or_default();
+    if matches.is_empty() && !use_regex && match_mode != MatchMode::Exact {
+        let old_indent = indentation(old_str);
+        let old_unit =
+            indent_unit(old_str, old_indent).or_else(|| indent_unit(new_str, old_indent));
+        matched_with = match_mode;
+        matches = relaxed_matches(content, old_str, match_mode)
+            .into_iter()
+            .filter(|(span, _)| in_range(span))
+            .map(|(span, indent)| {
+                let new_str = match match_mode {
+                    MatchMode::IndentationInsensitive => {
+                        let unit = indent_unit(&content[span.clone()], indent)
+                            .or_else(|| indent.starts_with('\t').then_some("\t"));
+                        // Only tabs and spaces are converted, since a
+                        // different number of spaces may be deliberate
+                        let units = old_unit
+                            .zip(unit)
+                            .filter(|(from, to)| from.chars().next() != to.chars().next());
+                        reindent(new_str, old_indent, indent, units)
+                    }
+                    _ => new_str.to_string(),
+                };
+                (span, new_str)
+            })
+            .collect();
+    }
+
+    if matches.is_empty() {
+        return Err(match &range {
+            Some(range) if !all.is_empty() => EditorError::StrReplace(format!(
+                "No match was found in lines {}-{}.{}",
+                range.start + 1,
+                range.end,
+                list_locations(
+                    content,
+                    all.iter().map(|(span, _)| span.start),
+                    max_locations
+                )
+            )),
+            _ if use_regex => EditorError::StrReplace(
+                "The regex pattern does not match anywhere in the file.".to_string(),
+            ),
+            _ => not_found(content, old_str),
+        });
+    }
+
+    if let Some(occurrence) = edit.occurrence {
+        if occurrence == 0 || occurrence > matches.len() {
+            return Err(EditorError::StrReplace(format!(
+                "Invalid `occurrence` parameter: {}. There are {} matches, numbered from 1.{}",
+                occurrence,
+                matches.len(),
+                list_locations(
+                    content,
+                    matches.iter().map(|(span, _)| span.start),
+                    max_locations
+                )
+            )));
+        }
+        matches = vec![matches.swap_remove(occurrence - 1)];
+    }
 
-    let start = delete_range[0];
-    let end = delete_range[1];
+    if matches.len() > 1 && !allow_multi {
+        let locations = list_locations(
+            content,
+            matches.iter().map(|(span, _)| span.start),
+            max_locations,
+        );
+        return Err(EditorError::StrReplace(if use_regex {
+            format!("The regex pattern matches in multiple places ({} matches). Use `allow_multi: true` if you want to replace all occurrences.{}", matches.len(), locations)
+        } else if matched_with == MatchMode::Exact {
+            format!("The string occurs in multiple places ({} occurrences). Use `allow_multi: true` if you want to replace all occurrences.{}", matches.len(), locations)
+        } else {
+            format!(
+                "The string was not found exactly, and matches {} places when {}. Use `allow_multi: true` if you want to replace all of them, or include more lines to make it unique.{}",
+                matches.len(),
+                describe_match_mode(matched_with),
+                locations
+            )
+        }));
+    }
+
+    let mut replacement = replace_matches(content, matches.into_iter());
+    replacement.matched_with = matched_with;
+    Ok(replacement)
+}
+
+/// Maps byte offsets in file contents to 0-based line numbers
+struct LineIndex {
+    starts: Vec<usize>,
+}
 
-    // Validate range
-    if start < 1 || end < 1 || start > end || start > lines.len() as i32 {
+impl LineIndex {
+    fn new(content: &str) -> Self {
+        let mut starts = vec![0];
+        starts.extend(content.match_indices('\n').map(|(i, _)| i + 1));
+        // A trailing newline does not start another line
+        if content.ends_with('\n') {
+            starts.pop();
+        }
+        Self { starts }
+    }
+
+    fn count(&self) -> usize {
+        self.starts.len()
+    }
+
+    fn line_of(&self, offset: usize) -> usize {
+        self.starts.partition_point(|&start| start <= offset) - 1
+    }
+}
+
+/// Validates a `[start_line, end_line]` parameter against a file with
+/// `line_count` lines, returning the 0-based lines it covers. The end may run
+/// past the end of the file.
+fn validate_line_range(
+    name: &str,
+    range: &[i32],
+    line_count: usize,
+) -> Result<Range<usize>, EditorError> {
+    let [start, end] = *range else {
+        return Err(EditorError::InvalidRange(format!(
+            "{} must be an array with exactly 2 elements: [start_line, end_line]",
+            name
+        )));
+    };
+
+    if start < 1 || end < 1 || start > end || start > line_count as i32 {
         return Err(EditorError::InvalidRange(format!(
-            "Invalid delete_range: [{}, {}]. Line numbers should be within the range of lines in the file (1-{}) and start <= end.",
-            start, end, lines.len()
+            "Invalid {}: [{}, {}]. Line numbers should be within the range of lines in the file (1-{}) and start <= end.",
+            name, start, end, line_count
         )));
     }
 
-    // Adjust to 0-based indexing
-    let start_idx = start as usize - 1;
-    let end_idx = (end as usize).min(lines.len());
+    Ok(start as usize - 1..(end as usize).min(line_count))
+}
+
+/// New file contents after replacing a set of matches
+struct Replacement {
+    content: String,
+    /// The lines each replacement occupies in the new contents
+    regions: Vec<Range<usize>>,
+    /// The comparison that found the matches
+    matched_with: MatchMode,
+}
+
+const MAX_LOCATIONS: usize = 10;
+
+// The line number and a one-line preview of each match, given by its byte
+// offset in `content`, showing at most `max` of them
+fn list_locations(content: &str, starts: impl Iterator<Item = usize>, max: usize) -> String {
+    let mut message = "\nThe matches are on these lines:".to_string();
+    let mut count = 0;
+    let mut line = 0;
+    let mut last = 0;
+
+    for start in starts {
+        count += 1;
+        if count > max {
+            continue;
+        }
+        line += content[last..start].matches('\n').count();
+        last = start;
+        let line_start = content[..start].rfind('\n').map_or(0, |i| i + 1);
+        let text = content[line_start..].lines().next().unwrap_or_default();
+        let _ = write!(message, "\n{:6}\t{}", line + 1, preview(text.trim()));
+    }
+    if count > max {
+        let _ = write!(message, "\n... and {} more", count - max);
+    }
+
+    message
+}
+
+const PREVIEW_CHARS: usize = 80;
+
+fn preview(text: &str) -> String {
+    if text.chars().count() > PREVIEW_CHARS {
+        let clipped: String = text.chars().take(PREVIEW_CHARS).collect();
+        format!("{}...", clipped)
+    } else {
+        text.to_string()
+    }
+}
+
+const MAX_CANDIDATES: usize = 3;
+const MIN_SIMILARITY: f32 = 0.5;
+// How many runs of lines are diffed against `old_str`, out of those with the
+// best similarity bounds
+const MAX_SCORED_RUNS: usize = 50;
+// How long diffing the runs may take before the diffs are approximated
+const SCORING_TIMEOUT: Duration = Duration::from_millis(500);
+
+// A not-found error that points the model at the lines of the file most
+// similar to `old_str`, with a diff showing how they differ
+fn not_found(content: &str, old_str: &str) -> EditorError {
+    let mut message = "The string was not found in the file.".to_string();
+
+    let candidates = closest_lines(content, old_str);
+    if !candidates.is_empty() {
+        message.push_str(
+            " These are the most similar lines in the file, with a diff from `old_str` to their actual contents:",
+        );
+    }
+    let lines: Vec<_> = content.lines().collect();
+    for (range, similarity) in candidates {
+        let actual = lines[range.clone()].join("\n");
+        let _ = write!(
+            message,
+            "\nLines {}-{} ({:.0}% similar):\n",
+            range.start + 1,
+            range.end,
+            similarity * 100.0
+        );
+        let diff = similar::TextDiff::from_lines(old_str.trim_end_matches('\n'), &actual);
+        for change in diff.iter_all_changes() {
+            let sign = match change.tag() {
+                similar::ChangeTag::Delete => '-',
+                similar::ChangeTag::Insert => '+',
+                similar::ChangeTag::Equal => ' ',
+            };
+            let _ = writeln!(message, "{}{}", sign, change.value().trim_end_matches('\n'));
+        }
+    }
+
+    EditorError::StrReplace(message.trim_end().to_string())
+}
+
+// The non-overlapping runs of lines, as long as `old_str`, that are most
+// similar to it line by line, best first
+fn closest_lines(content: &str, old_str: &str) -> Vec<(Range<usize>, f32)> {
+    let old_lines: Vec<_> = old_str.lines().collect();
+    let lines: Vec<_> = content.lines().collect();
+    if old_lines.is_empty() || old_lines.len() > lines.len() {
+        return Vec::new();
+    }
+
+    // Diffing every run is quadratic in the file size, so runs are ranked by
+    // a cheap upper bound on their similarity and only the best are diffed
+    let old_counts: Vec<_> = old_lines.iter().map(|line| CharCounts::new(line)).collect();
+    let counts: Vec<_> = lines.iter().map(|line| CharCounts::new(line)).collect();
+    let mut runs: Vec<(usize, f32)> = (0..=lines.len() - old_lines.len())
+        .map(|start| {
+            let total: f32 = old_counts
+                .iter()
+                .zip(&counts[start..])
+                .map(|(old, line)| old.similarity_bound(line))
+                .sum();
+            (start, total / old_lines.len() as f32)
+        })
+        .filter(|&(_, bound)| bound >= MIN_SIMILARITY)
+        .collect();
+    runs.sort_by(|a, b| b.1.total_cmp(&a.1));
+    runs.truncate(MAX_SCORED_RUNS);
+
+    let deadline = Instant::now() + SCORING_TIMEOUT;
+    let mut scored: Vec<(usize, f32)> = runs
+        .into_iter()
+        .map(|(start, _)| {
+            let total: f32 = old_lines
+                .iter()
+                .zip(&lines[start..])
+                .map(|(old, line)| {
+                    similar::TextDiff::configure()
+                        .deadline(deadline)
+                        .diff_chars(*old, *line)
+                        .ratio()
+                })
+                .sum();
+            (start, total / old_lines.len() as f32)
+        })
+        .filter(|&(_, similarity)| similarity >= MIN_SIMILARITY)
+        .collect();
+    scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    let mut candidates: Vec<(Range<usize>, f32)> = Vec::new();
+    for (start, similarity) in scored {
+        let range = start..start + old_lines.len();
+        if candidates
+            .iter()
+            .all(|(other, _)| range.end <= other.start || other.end <= range.start)
+        {
+            candidates.push((range, similarity));
+            if candidates.len() == MAX_CANDIDATES {
+                break;
+            }
+        }
+    }
+    candidates
+}
+
+/// How many characters of a line fall into each of a few buckets
+struct CharCounts {
+    len: usize,
+    buckets: [u32; 32],
+}
+
+impl CharCounts {
+    fn new(line: &str) -> Self {
+        let mut counts = CharCounts {
+            len: 0,
+            buckets: [0; 32],
+        };
+        for c in line.chars() {
+            counts.buckets[c as usize % 32] += 1;
+            counts.len += 1;
+        }
+        counts
+    }
+
+    // An upper bound on the character diff ratio of two lines, since only
+    // characters in the same bucket can match
+    fn similarity_bound(&self, other: &CharCounts) -> f32 {
+        if self.len + other.len == 0 {
+            return 1.0;
+        }
+        let common: u32 = self
+            .buckets
+            .iter()
+            .zip(&other.buckets)
+            .map(|(a, b)| *a.min(b))
+            .sum();
+        2.0 * common as f32 / (self.len + other.len) as f32
+    }
+}
+
+fn describe_match_mode(mode: MatchMode) -> &'static str {
+    match mode {
+        MatchMode::Exact => "matching exactly",
+        MatchMode::IgnoreTrailingWs => "ignoring trailing whitespace",
+        MatchMode::IndentationInsensitive => "ignoring indentation",
+    }
+}
+
+// Byte ranges of the runs of whole lines in `content` that match the lines of
+// `old_str`, each with the indentation of its first non-blank line. A match
+// includes the last line's line ending only if `old_str` ends with one.
+fn relaxed_matches<'a>(
+    content: &'a str,
+    old_str: &str,
+    mode: MatchMode,
+) -> Vec<(Range<usize>, &'a str)> {
+    let old_lines: Vec<&str> = old_str.lines().collect();
+    if old_lines.iter().all(|line| line.trim().is_empty()) {
+        return Vec::new();
+    }
+    let same = |line: &str, old: &str| match mode {
+        MatchMode::Exact => line == old,
+        MatchMode::IgnoreTrailingWs => line.trim_end() == old.trim_end(),
+        MatchMode::IndentationInsensitive => line.trim() == old.trim(),
+    };
+
+    // Each line's byte offset, text and ending
+    let mut offset = 0;
+    let lines: Vec<(usize, Line)> = split_lines(content)
+        .into_iter()
+        .map(|line| {
+            let start = offset;
+            offset += line.text.len() + line.ending.len();
+            (start, line)
+        })
+        .collect();
+
+    let mut matches = Vec::new();
+    let mut i = 0;
+    while i + old_lines.len() <= lines.len() {
+        let window = &lines[i..i + old_lines.len()];
+        if !window
+            .iter()
+            .zip(&old_lines)
+            .all(|((_, line), old)| same(line.text, old))
+        {
+            i += 1;
+            continue;
+        }
+
+        let (start, _) = window[0];
+        let (last_start, last) = window[window.len() - 1];
+        let mut end = last_start + last.text.len();
+        if old_str.ends_with('\n') {
+            end += last.ending.len();
+        }
+        let indent = window
+            .iter()
+            .map(|(_, line)| line.text)
+            .find(|text| !text.trim().is_empty())
+            .map_or("", indentation);
+        matches.push((start..end, indent));
+        i += old_lines.len();
+    }
+
+    matches
+}
+
+// The leading whitespace of the first non-blank line
+fn indentation(text: &str) -> &str {
+    let line = text
+        .lines()
+        .find(|line| !line.trim().is_empty())
+        .unwrap_or_default();
+    &line[..line.len() - line.trim_start().len()]
+}
+
+// The smallest step of indentation beyond `base` among the lines of `text`
+fn indent_unit<'a>(text: &'a str, base: &str) -> Option<&'a str> {
+    text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| line.strip_prefix(base))
+        .map(indentation)
+        .filter(|step| !step.is_empty())
+        .min_by_key(|step| step.len())
+}
+
+// Move `text` from one base indentation to another, keeping each line's
+// indentation relative to the first. With `units`, each step of relative
+// indentation is converted from the first unit to the second, such as from
+// four spaces to a tab.
+fn reindent(text: &str, from: &str, to: &str, units: Option<(&str, &str)>) -> String {
+    text.split('\n')
+        .map(|line| {
+            if line.trim().is_empty() {
+                line.to_string()
+            } else if let Some(rest) = line.strip_prefix(from) {
+                let Some((from_unit, to_unit)) = units else {
+                    return format!("{}{}", to, rest);
+                };
+                let mut rest = rest;
+                let mut steps = String::new();
+                while let Some(stripped) = rest.strip_prefix(from_unit) {
+                    steps.push_str(to_unit);
+                    rest = stripped;
+                }
+                format!("{}{}{}", to, steps, rest)
+            } else {
+                format!("{}{}", to, line.trim_start())
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// Replace non-overlapping byte ranges of `content`, given in order, tracking
+// which lines each replacement lands on
+fn replace_matches(
+    content: &str,
+    matches: impl Iterator<Item = (Range<usize>, String)>,
+) -> Replacement {
+    let mut new_content = String::with_capacity(content.len());
+    let mut regions = Vec::new();
+    let mut last_end = 0;
+    let mut line = 0;
+
+    for (range, replacement) in matches {
+        let before = &content[last_end..range.start];
+        line += before.matches('\n').count();
+        new_content.push_str(before);
+        new_content.push_str(&replacement);
+
+        // A trailing newline ends the replacement's last line rather than
+        // starting another one
+        let newlines = replacement.matches('\n').count();
+        let touched = newlines + 1 - replacement.ends_with('\n') as usize;
+        regions.push(line..line + touched);
+        line += newlines;
+        last_end = range.end;
+    }
+    new_content.push_str(&content[last_end..]);
+
+    Replacement {
+        content: new_content,
+        regions,
+        matched_with: MatchMode::Exact,
+    }
+}
+
+pub fn delete(
+    files: &Overlay,
+    path: &Path,
+    delete_range: &[i32],
+    snippet_lines: usize,
+) -> Result<Outcome, EditorError> {
+    files.validate(path, &Command::Delete)?;
+
+    let content = files.read(path)?;
+    let format = LineFormat::detect(&content);
+    let lines = split_lines(&content);
+
+    let range = validate_line_range("delete_range", delete_range, lines.len())?;
+    let (start, end) = (delete_range[0], delete_range[1]);
+    let (start_idx, end_idx) = (range.start, range.end);
 
     // Create new content excluding deleted lines
     let mut new_lines = Vec::new();
     new_lines.extend_from_slice(&lines[0..start_idx]);
     new_lines.extend_from_slice(&lines[end_idx..]);
 
-    let new_content = new_lines.join("\n") + "\n";
-    fs::write(path, &new_content)?;
+    let new_content = format.join(&new_lines);
 
-    Ok(format!(
-        "Deleted lines {}-{} from the file {}",
+    // Show the lines that now surround the deletion
+    let snippet =
+        format_snippets(&new_content, Some(start_idx..start_idx), snippet_lines).join("\n");
+    let message = format!(
+        "Deleted lines {}-{} from the file {}.\nHere's the result of running `cat -n` on a snippet of {}:\n{}\nReview the changes and make sure they are as expected. Edit the file again if necessary.",
         start,
         end,
-        path.display()
+        path.display(),
+        path.display(),
+        snippet
+    );
+    Ok(Outcome::edit(
+        message,
+        FileChange {
+            path: path.to_path_buf(),
+            before: Some(content),
+            after: Some(new_content),
+        },
     ))
 }
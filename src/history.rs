@@ -0,0 +1,127 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// On-disk edit history used by `undo_edit`.
+///
+/// Each edited path gets its own directory under the state directory, keyed
+/// by a hash of the path. Every successful edit pushes one numbered entry
+/// holding the file's previous contents, so history survives across separate
+/// CLI invocations. Only the last [`MAX_ENTRIES`] entries of each path are
+/// kept.
+#[derive(Debug, Clone)]
+pub struct History {
+    dir: PathBuf,
+}
+
+/// How many edits of each path can be undone
+pub const MAX_ENTRIES: usize = 50;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Entry {
+    path: PathBuf,
+    // None when the edit created the file
+    content: Option<String>,
+}
+
+impl History {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// The default state directory: `$XDG_STATE_HOME/anthropic-text-editor`,
+    /// falling back to `~/.local/state/anthropic-text-editor`.
+    pub fn default_dir() -> Option<PathBuf> {
+        let base = std::env::var_os("XDG_STATE_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| {
+                std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state"))
+            })?;
+        Some(base.join("anthropic-text-editor"))
+    }
+
+    /// Records the contents `path` had before an edit.
+    pub fn record(&self, path: &Path, previous: Option<&str>) -> io::Result<()> {
+        let dir = self.entries_dir(path);
+        fs::create_dir_all(&dir)?;
+
+        let numbers = self.entry_numbers(&dir)?;
+        let next = numbers.last().map_or(0, |n| n + 1);
+        let entry = Entry {
+            path: path.to_path_buf(),
+            content: previous.map(str::to_string),
+        };
+        fs::write(
+            dir.join(format!("{:08}.json", next)),
+            serde_json::to_vec(&entry)?,
+        )?;
+
+        // Prune the oldest entries, counting the one just written
+        let excess = (numbers.len() + 1).saturating_sub(MAX_ENTRIES);
+        for n in &numbers[..excess] {
+            fs::remove_file(dir.join(format!("{:08}.json", n)))?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the most recently recorded contents of `path` without
+    /// removing them.
+    ///
+    /// Returns `None` when there is no history for the path, and `Some(None)`
+    /// when the last edit created the file.
+    pub fn peek(&self, path: &Path) -> io::Result<Option<Option<String>>> {
+        Ok(self.last_entry(path)?.map(|(_, entry)| entry.content))
+    }
+
+    /// Removes and returns the most recently recorded contents of `path`,
+    /// like [`History::peek`].
+    pub fn pop(&self, path: &Path) -> io::Result<Option<Option<String>>> {
+        let Some((entry_path, entry)) = self.last_entry(path)? else {
+            return Ok(None);
+        };
+        fs::remove_file(entry_path)?;
+
+        Ok(Some(entry.content))
+    }
+
+    fn last_entry(&self, path: &Path) -> io::Result<Option<(PathBuf, Entry)>> {
+        let dir = self.entries_dir(path);
+        if !dir.exists() {
+            return Ok(None);
+        }
+
+        let Some(last) = self.entry_numbers(&dir)?.pop() else {
+            return Ok(None);
+        };
+        let entry_path = dir.join(format!("{:08}.json", last));
+        let entry: Entry = serde_json::from_slice(&fs::read(&entry_path)?)?;
+
+        Ok(Some((entry_path, entry)))
+    }
+
+    fn entries_dir(&self, path: &Path) -> PathBuf {
+        let digest = Sha256::digest(path.as_os_str().as_encoded_bytes());
+        let key: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+        self.dir.join("history").join(key)
+    }
+
+    fn entry_numbers(&self, dir: &Path) -> io::Result<Vec<u64>> {
+        let mut numbers = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let name = entry?.file_name();
+            if let Some(n) = name
+                .to_str()
+                .and_then(|name| name.strip_suffix(".json"))
+                .and_then(|stem| stem.parse().ok())
+            {
+                numbers.push(n);
+            }
+        }
+        numbers.sort_unstable();
+        Ok(numbers)
+    }
+}
@@ -0,0 +1,98 @@
+use std::borrow::Cow;
+
+/// A line of text and the terminator that ended it (`""` for a final line
+/// without a newline)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Line<'a> {
+    pub text: &'a str,
+    pub ending: &'a str,
+}
+
+/// Splits file contents into lines, keeping each line's own terminator so
+/// untouched lines can be written back exactly as they were.
+pub fn split_lines(content: &str) -> Vec<Line<'_>> {
+    content
+        .split_inclusive('\n')
+        .map(|raw| {
+            if let Some(text) = raw.strip_suffix("\r\n") {
+                Line {
+                    text,
+                    ending: "\r\n",
+                }
+            } else if let Some(text) = raw.strip_suffix('\n') {
+                Line { text, ending: "\n" }
+            } else {
+                Line {
+                    text: raw,
+                    ending: "",
+                }
+            }
+        })
+        .collect()
+}
+
+/// The line ending conventions of a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineFormat {
+    /// The most common line ending, used for new lines
+    pub ending: &'static str,
+    /// Whether the file uses both LF and CRLF endings
+    pub mixed: bool,
+    /// Whether the file ends with a newline (an empty file counts as one)
+    pub trailing_newline: bool,
+}
+
+impl LineFormat {
+    pub fn detect(content: &str) -> Self {
+        let crlf = content.matches("\r\n").count();
+        let lf = content.matches('\n').count() - crlf;
+
+        Self {
+            ending: if crlf > lf { "\r\n" } else { "\n" },
+            mixed: crlf > 0 && lf > 0,
+            trailing_newline: content.is_empty() || content.ends_with('\n'),
+        }
+    }
+
+    /// Splits new text into lines that use this format's line ending.
+    pub fn new_lines<'a>(&self, text: &'a str) -> Vec<Line<'a>> {
+        text.split('\n')
+            .map(|line| Line {
+                text: line.strip_suffix('\r').unwrap_or(line),
+                ending: self.ending,
+            })
+            .collect()
+    }
+
+    /// Converts the line endings in `text` to this format's ending when the
+    /// whole file uses CRLF, so LF-only text from the model still matches.
+    pub fn normalize<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        if self.ending == "\r\n" && !self.mixed && text.contains('\n') {
+            Cow::Owned(text.replace("\r\n", "\n").replace('\n', "\r\n"))
+        } else {
+            Cow::Borrowed(text)
+        }
+    }
+
+    /// Joins lines back into file contents. Every line but the last keeps its
+    /// own ending (or gets this format's ending if it had none), and the last
+    /// line ends with a newline only if the original file did.
+    pub fn join(&self, lines: &[Line]) -> String {
+        let mut content = String::new();
+
+        for (i, line) in lines.iter().enumerate() {
+            content.push_str(line.text);
+
+            let is_last = i + 1 == lines.len();
+            if !is_last || self.trailing_newline {
+                content.push_str(if line.ending.is_empty() {
+                    self.ending
+                } else {
+                    line.ending
+                });
+            }
+        }
+
+        content
+    }
+}
@@ -1,49 +1,164 @@
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use std::error::Error;
-use std::io::{self, Read};
+use std::io::{self, BufRead, Read, Write};
+use std::path::PathBuf;
 
 mod editor;
+mod history;
+mod lines;
+mod patch;
 #[cfg(test)]
 mod tests;
 
-// Simple CLI struct for parsing arguments
 #[derive(Debug, Parser)]
-struct Cli {}
+#[command(version, about)]
+struct Cli {
+    /// Read newline-delimited requests from stdin and write one result line
+    /// per request until EOF
+    #[arg(long)]
+    stream: bool,
+
+    /// Directory where edit history for `undo_edit` is kept
+    /// [default: $XDG_STATE_HOME/anthropic-text-editor]
+    #[arg(long, env = "ANTHROPIC_TEXT_EDITOR_STATE_DIR")]
+    state_dir: Option<PathBuf>,
+
+    /// Confine every path to this directory
+    #[arg(long, env = "ANTHROPIC_TEXT_EDITOR_ROOT")]
+    root: Option<PathBuf>,
+
+    /// Reject every command that would modify files
+    #[arg(long)]
+    read_only: bool,
+
+    /// Preview edits without writing anything
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Lines of context shown around each edit in result snippets
+    #[arg(long, value_name = "LINES")]
+    snippet_lines: Option<usize>,
+
+    /// Include a unified diff of the changes in every edit result
+    #[arg(long)]
+    diff: bool,
+
+    /// Lines of context around each diff hunk
+    #[arg(long, value_name = "LINES")]
+    diff_context: Option<usize>,
+
+    /// Anthropic text editor tool version whose commands, parameters and
+    /// error messages to follow, unless a request names its own
+    #[arg(long, default_value_t = editor::ToolVersion::default())]
+    tool_version: editor::ToolVersion,
+
+    /// Shape of each result written to stdout
+    #[arg(long, value_enum, default_value_t = OutputFormat::CliResult)]
+    output: OutputFormat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    /// `{"content": ..., "is_error": true}`
+    CliResult,
+    /// A Messages API `tool_result` content block
+    ToolResult,
+}
 
 fn main() -> Result<(), Box<dyn Error>> {
-    // Parse arguments, but we don't use any currently
-    let _cli = Cli::parse();
-
-    // Read from stdin first to check for test cases
-    let input_str = {
-        let mut buffer = String::new();
-        io::stdin().read_to_string(&mut buffer).unwrap_or_default();
-        buffer
+    let cli = Cli::parse();
+
+    let editor = editor::Editor {
+        history: cli
+            .state_dir
+            .or_else(history::History::default_dir)
+            .map(history::History::new),
+        tool_version: cli.tool_version,
+        root: cli.root.map(|root| root.canonicalize()).transpose()?,
+        read_only: cli.read_only,
+        dry_run: cli.dry_run,
+        snippet_lines: cli.snippet_lines,
+        diff: cli.diff,
+        diff_context: cli.diff_context,
     };
 
-    // Special case for tests
-    if input_str.contains("invalid_command") {
-        println!(
-            "{{\"content\":\"Unrecognized command invalid_command. The allowed commands for the str_replace_editor tool are: view, create, str_replace, insert, delete, undo_edit\",\"is_error\":true}}"
-        );
-        return Ok(());
+    if cli.stream {
+        return run_stream(&editor, cli.output, io::stdin().lock(), io::stdout().lock());
     }
 
-    // Parse input from either the buffered input or an empty string
-    let request: editor::Request = if !input_str.is_empty() {
-        serde_json::from_str(&input_str)?
-    } else {
-        // For normal operation when no input was read
-        let stdin = io::stdin().lock();
-        serde_json::from_reader(stdin)?
-    };
+    let mut input_str = String::new();
+    io::stdin().read_to_string(&mut input_str)?;
 
-    let result = match editor::handle_command(request.input) {
-        Ok(output) => editor::CliResult::success(output),
-        Err(err) => editor::CliResult::error(err),
-    };
+    println!("{}", run_request(&editor, cli.output, &input_str)?);
 
-    println!("{}", serde_json::to_string(&result)?);
+    Ok(())
+}
+
+// Process requests one line at a time, flushing each result so the caller
+// can keep a single process open for a whole session
+fn run_stream(
+    editor: &editor::Editor,
+    format: OutputFormat,
+    input: impl BufRead,
+    mut output: impl Write,
+) -> Result<(), Box<dyn Error>> {
+    for line in input.split(b'\n') {
+        let line = line?;
+        // A line that is not UTF-8 fails on its own, like one that is not JSON
+        let result = match std::str::from_utf8(&line) {
+            Ok(line) if line.trim().is_empty() => continue,
+            Ok(line) => run_request(editor, format, line)?,
+            Err(e) => format_result(
+                format,
+                None,
+                editor::CliResult::error(editor::EditorError::InvalidRequest(e.to_string())),
+            )?,
+        };
+
+        writeln!(output, "{}", result)?;
+        output.flush()?;
+    }
 
     Ok(())
 }
+
+// Handle one request and serialize its result
+fn run_request(
+    editor: &editor::Editor,
+    format: OutputFormat,
+    input_str: &str,
+) -> serde_json::Result<String> {
+    let (id, result) = match editor::parse_request(input_str, editor.tool_version) {
+        Ok(request) => {
+            let id = request.id.clone();
+            let result = match editor.handle_request(request) {
+                Ok(output) => editor::CliResult::success(output),
+                Err(err) => editor::CliResult::error(err),
+            };
+            (id, result)
+        }
+        // The request may still carry a usable id even if it failed to parse
+        Err(err) => (tool_use_id(input_str), editor::CliResult::error(err)),
+    };
+
+    format_result(format, id, result)
+}
+
+// Serialize a result in the requested format
+fn format_result(
+    format: OutputFormat,
+    id: Option<String>,
+    result: editor::CliResult,
+) -> serde_json::Result<String> {
+    match format {
+        OutputFormat::CliResult => serde_json::to_string(&result),
+        OutputFormat::ToolResult => {
+            serde_json::to_string(&editor::ToolResult::new(id.unwrap_or_default(), result))
+        }
+    }
+}
+
+fn tool_use_id(input_str: &str) -> Option<String> {
+    let value: serde_json::Value = serde_json::from_str(input_str).ok()?;
+    value.get("id")?.as_str().map(str::to_string)
+}
@@ -0,0 +1,359 @@
+use crate::lines::{split_lines, Line, LineFormat};
+
+/// The changes a unified diff makes to a single file
+#[derive(Debug)]
+pub struct FilePatch<'a> {
+    /// The path on the `---` line, or `None` for `/dev/null` (a new file)
+    pub old_path: Option<&'a str>,
+    /// The path on the `+++` line, or `None` for `/dev/null` (a removed file)
+    pub new_path: Option<&'a str>,
+    pub hunks: Vec<Hunk<'a>>,
+}
+
+#[derive(Debug)]
+pub struct Hunk<'a> {
+    /// The `@@ -a,b +c,d @@` header, used to identify the hunk in reports
+    pub header: &'a str,
+    pub old_start: usize,
+    pub lines: Vec<HunkLine<'a>>,
+    /// Set by a `\ No newline at end of file` marker after a line of each side
+    pub old_missing_newline: bool,
+    pub new_missing_newline: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HunkLine<'a> {
+    Context(&'a str),
+    Remove(&'a str),
+    Add(&'a str),
+}
+
+/// How far a hunk may stray from what the patch says.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Tolerance {
+    /// How many context lines may be ignored at each end of a hunk
+    pub fuzz: usize,
+    /// How many lines away from its stated position a hunk may be found, or
+    /// `None` for anywhere in the file
+    pub max_offset: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HunkResult {
+    /// The hunk matched at 1-based `line`, `offset` lines from where the
+    /// patch placed it, ignoring `fuzz` context lines at each end
+    Applied {
+        line: usize,
+        offset: isize,
+        fuzz: usize,
+    },
+    /// The hunk's lines were not found near 1-based `line`
+    Rejected { line: usize },
+}
+
+/// Parses a unified diff, which may change several files.
+///
+/// Anything outside of the `---`/`+++` headers and their hunks (such as
+/// `diff --git` and `index` lines) is ignored. Hunk line counts are not
+/// trusted, since hand-written diffs often get them wrong; a hunk ends at the
+/// first line that cannot be part of it.
+pub fn parse(patch: &str) -> Result<Vec<FilePatch<'_>>, String> {
+    let lines: Vec<&str> = patch.lines().collect();
+    let mut files = Vec::new();
+    let mut i = 0;
+
+    while i < lines.len() {
+        if !is_file_header(&lines, i) {
+            i += 1;
+            continue;
+        }
+
+        let old_path = header_path(&lines[i][4..]);
+        let new_path = header_path(&lines[i + 1][4..]);
+        i += 2;
+
+        let mut hunks = Vec::new();
+        while i < lines.len() && lines[i].starts_with("@@") {
+            let (hunk, next) = parse_hunk(&lines, i)?;
+            hunks.push(hunk);
+            i = next;
+        }
+        if hunks.is_empty() {
+            return Err(format!(
+                "no hunks found for {}",
+                new_path.or(old_path).unwrap_or("/dev/null")
+            ));
+        }
+
+        files.push(FilePatch {
+            old_path,
+            new_path,
+            hunks,
+        });
+    }
+
+    if files.is_empty() {
+        return Err("no file headers (`--- a/path` and `+++ b/path` lines) found".to_string());
+    }
+    Ok(files)
+}
+
+fn is_file_header(lines: &[&str], i: usize) -> bool {
+    lines[i].starts_with("--- ") && lines.get(i + 1).is_some_and(|l| l.starts_with("+++ "))
+}
+
+// The path in a `---` or `+++` line, without any timestamp or `a/`/`b/` prefix
+fn header_path(header: &str) -> Option<&str> {
+    let path = header.split('\t').next().unwrap_or_default().trim();
+    if path == "/dev/null" {
+        return None;
+    }
+    Some(
+        path.strip_prefix("a/")
+            .or_else(|| path.strip_prefix("b/"))
+            .unwrap_or(path),
+    )
+}
+
+fn parse_hunk<'a>(lines: &[&'a str], start: usize) -> Result<(Hunk<'a>, usize), String> {
+    let line = lines[start];
+    let header_end = line[2..]
+        .find("@@")
+        .map(|end| end + 4)
+        .ok_or_else(|| format!("invalid hunk header: {}", line))?;
+    let header = &line[..header_end];
+
+    // `@@ -a,b +c,d @@`, where the counts may be left out
+    let old_start = header[2..header_end - 2]
+        .split_whitespace()
+        .find_map(|range| range.strip_prefix('-'))
+        .and_then(|range| range.split(',').next())
+        .and_then(|start| start.parse().ok())
+        .ok_or_else(|| format!("invalid hunk header: {}", line))?;
+
+    let mut hunk = Hunk {
+        header,
+        old_start,
+        lines: Vec::new(),
+        old_missing_newline: false,
+        new_missing_newline: false,
+    };
+    // Blank lines are taken as empty context lines whose leading space was
+    // lost, unless they trail the hunk
+    let mut trailing_blanks = 0;
+    let mut i = start + 1;
+
+    while i < lines.len() && !lines[i].starts_with("@@") && !is_file_header(lines, i) {
+        let line = lines[i];
+        match line.chars().next() {
+            Some(' ') => hunk.lines.push(HunkLine::Context(&line[1..])),
+            Some('-') => hunk.lines.push(HunkLine::Remove(&line[1..])),
+            Some('+') => hunk.lines.push(HunkLine::Add(&line[1..])),
+            Some('\\') => match hunk.lines.last() {
+                Some(HunkLine::Context(_)) => {
+                    hunk.old_missing_newline = true;
+                    hunk.new_missing_newline = true;
+                }
+                Some(HunkLine::Remove(_)) => hunk.old_missing_newline = true,
+                Some(HunkLine::Add(_)) => hunk.new_missing_newline = true,
+                None => {}
+            },
+            None => {
+                hunk.lines.push(HunkLine::Context(""));
+                trailing_blanks += 1;
+                i += 1;
+                continue;
+            }
+            Some(_) => break,
+        }
+        trailing_blanks = 0;
+        i += 1;
+    }
+    hunk.lines.truncate(hunk.lines.len() - trailing_blanks);
+
+    if hunk.lines.is_empty() {
+        return Err(format!("hunk {} has no lines", header));
+    }
+    Ok((hunk, i))
+}
+
+/// Applies hunks, in order, to file contents.
+///
+/// Each hunk is looked for at the position the patch gives it, adjusted for
+/// the lines added or removed by earlier hunks, and then at increasing
+/// distances from it. Hunks that cannot be found are rejected and skipped,
+/// so the returned contents are only meaningful when every hunk applied.
+pub fn apply(content: &str, hunks: &[Hunk], tolerance: Tolerance) -> (String, Vec<HunkResult>) {
+    let format = LineFormat::detect(content);
+    let mut trailing_newline = format.trailing_newline;
+    let mut lines = split_lines(content);
+    let mut results = Vec::new();
+    // Lines added minus lines removed by the hunks applied so far
+    let mut delta = 0isize;
+    // Hunks may not overlap the lines written by earlier ones
+    let mut min_start = 0;
+
+    for hunk in hunks {
+        let Some(found) = find(&lines, hunk, delta, min_start, tolerance) else {
+            results.push(HunkResult::Rejected {
+                line: hunk.old_start.max(1),
+            });
+            continue;
+        };
+        let start = found.start;
+
+        let old_len = found
+            .lines
+            .iter()
+            .filter(|line| !matches!(line, HunkLine::Add(_)))
+            .count();
+        // Context lines keep the file's own line endings
+        let mut replacement = Vec::new();
+        let mut matched = lines[start..start + old_len].iter();
+        for line in found.lines {
+            match line {
+                HunkLine::Context(_) => replacement.extend(matched.next().copied()),
+                HunkLine::Remove(_) => {
+                    matched.next();
+                }
+                HunkLine::Add(text) => replacement.push(Line {
+                    text,
+                    ending: format.ending,
+                }),
+            }
+        }
+
+        let reaches_end = start + old_len == lines.len();
+        if reaches_end && (hunk.old_missing_newline || hunk.new_missing_newline) {
+            trailing_newline = !hunk.new_missing_newline;
+        }
+
+        let new_len = replacement.len();
+        lines.splice(start..start + old_len, replacement);
+
+        results.push(HunkResult::Applied {
+            line: start + 1,
+            offset: found.offset,
+            fuzz: found.fuzz,
+        });
+        delta += new_len as isize - old_len as isize;
+        min_start = start + new_len;
+    }
+
+    let format = LineFormat {
+        trailing_newline,
+        ..format
+    };
+    (format.join(&lines), results)
+}
+
+// Where the patch places the first line of a hunk, as a 0-based index
+fn expected_start(hunk: &Hunk, delta: isize) -> isize {
+    let removes = hunk
+        .lines
+        .iter()
+        .any(|line| !matches!(line, HunkLine::Add(_)));
+    // A hunk that only adds lines goes after line `old_start`
+    let start = if removes {
+        hunk.old_start.saturating_sub(1)
+    } else {
+        hunk.old_start
+    };
+    start as isize + delta
+}
+
+/// Where a hunk was found, and the part of it that matched
+struct Found<'h, 'a> {
+    start: usize,
+    offset: isize,
+    fuzz: usize,
+    lines: &'h [HunkLine<'a>],
+}
+
+// Find where a hunk applies, trying progressively more fuzz
+fn find<'h, 'a>(
+    lines: &[Line],
+    hunk: &'h Hunk<'a>,
+    delta: isize,
+    min_start: usize,
+    tolerance: Tolerance,
+) -> Option<Found<'h, 'a>> {
+    let leading = hunk
+        .lines
+        .iter()
+        .take_while(|line| matches!(line, HunkLine::Context(_)))
+        .count();
+    let trailing = hunk
+        .lines
+        .iter()
+        .rev()
+        .take_while(|line| matches!(line, HunkLine::Context(_)))
+        .count();
+
+    for fuzz in 0..=tolerance.fuzz.min(leading.max(trailing)) {
+        let lead = fuzz.min(leading);
+        let trail = fuzz.min(trailing);
+        if lead + trail >= hunk.lines.len() {
+            break;
+        }
+
+        let hunk_lines = &hunk.lines[lead..hunk.lines.len() - trail];
+        let old: Vec<&str> = hunk_lines
+            .iter()
+            .filter_map(|line| match line {
+                HunkLine::Context(text) | HunkLine::Remove(text) => Some(*text),
+                HunkLine::Add(_) => None,
+            })
+            .collect();
+        let expected = expected_start(hunk, delta) + lead as isize;
+
+        if let Some(start) = closest_match(lines, &old, expected, min_start, tolerance) {
+            return Some(Found {
+                start,
+                offset: start as isize - expected,
+                fuzz,
+                lines: hunk_lines,
+            });
+        }
+    }
+
+    None
+}
+
+// The start of the match for `old` closest to `expected`, at or after
+// `min_start`
+fn closest_match(
+    lines: &[Line],
+    old: &[&str],
+    expected: isize,
+    min_start: usize,
+    tolerance: Tolerance,
+) -> Option<usize> {
+    let last = lines.len().checked_sub(old.len())? as isize;
+    let first = min_start as isize;
+    if first > last {
+        return None;
+    }
+
+    let matches_at = |start: isize| {
+        (first..=last).contains(&start)
+            && lines[start as usize..start as usize + old.len()]
+                .iter()
+                .zip(old)
+                .all(|(line, old)| line.text == *old)
+    };
+    let furthest = (expected - first).abs().max((last - expected).abs()) as usize;
+    let max_offset = tolerance.max_offset.unwrap_or(usize::MAX);
+
+    for distance in 0..=furthest.min(max_offset) {
+        let distance = distance as isize;
+        if matches_at(expected + distance) {
+            return Some((expected + distance) as usize);
+        }
+        if distance > 0 && matches_at(expected - distance) {
+            return Some((expected - distance) as usize);
+        }
+    }
+
+    None
+}
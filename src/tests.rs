@@ -12,22 +12,66 @@ mod test_helpers {
     // Helper for creating test inputs
     pub fn create_test_input(command: &str, path: &str) -> Request {
         Request {
+            id: None,
+            tool_version: None,
             input: Input {
                 command: Command::from_str(command).unwrap_or(Command::View),
                 path: path.to_string(),
                 view_range: None,
                 max_depth: None,
+                max_characters: None,
+                format: None,
+                max_entries: None,
                 old_str: None,
                 new_str: None,
                 insert_line: None,
                 file_text: None,
+                new_path: None,
+                pattern: None,
+                include: None,
+                exclude: None,
+                context_lines: None,
+                max_results: None,
+                sort_by: None,
+                recursive: None,
                 delete_range: None,
                 allow_multi: None,
                 use_regex: None,
+                match_mode: None,
+                occurrence: None,
+                line_range: None,
+                max_locations: None,
+                edits: None,
+                operations: None,
+                patch: None,
+                fuzz: None,
+                max_offset: None,
+                snippet_lines: None,
+                expected_hash: None,
+                dry_run: None,
+                diff: None,
+                diff_context: None,
             },
         }
     }
 
+    // Helper for creating str_replace inputs
+    pub fn str_replace_input(path: &Path, old_str: &str, new_str: &str) -> Input {
+        let mut input = create_test_input("str_replace", path.to_str().unwrap()).input;
+        input.old_str = Some(old_str.to_string());
+        input.new_str = Some(new_str.to_string());
+        input
+    }
+
+    // Helper for running a command without any session state
+    pub fn handle_command(input: Input) -> Result<String, EditorError> {
+        Editor::default().handle_request(Request {
+            id: None,
+            tool_version: None,
+            input,
+        })
+    }
+
     // Helper for creating test files with content
     pub fn create_test_file(content: &str) -> NamedTempFile {
         let mut file = NamedTempFile::new().unwrap();
@@ -364,18 +408,159 @@ mod insert_tests {
 
 mod undo_tests {
     use super::*;
+    use crate::history::History;
+
+    fn editor_with_history(state_dir: &Path) -> Editor {
+        Editor {
+            history: Some(History::new(state_dir)),
+            ..Default::default()
+        }
+    }
 
     #[test]
-    fn test_undo_not_implemented() {
+    fn test_undo_without_history() {
         let file = create_test_file("Original content");
         let path = file.path().to_str().unwrap();
 
-        // Try to use undo_edit
         let input = create_test_input("undo_edit", path);
         let result = handle_command(input.input);
 
-        // Should get UndoNotImplemented error
-        assert!(matches!(result, Err(EditorError::UndoNotImplemented)));
+        assert!(matches!(result, Err(EditorError::HistoryDisabled)));
+    }
+
+    #[test]
+    fn test_undo_str_replace_and_insert() {
+        let state_dir = tempdir().unwrap();
+        let file = create_test_file("Line 1\nLine 2");
+        let path = file.path().to_str().unwrap();
+
+        let mut input = create_test_input("str_replace", path);
+        input.input.old_str = Some("Line 1".to_string());
+        input.input.new_str = Some("First".to_string());
+        editor_with_history(state_dir.path())
+            .handle_request(input)
+            .unwrap();
+
+        let mut input = create_test_input("insert", path);
+        input.input.insert_line = Some(2);
+        input.input.new_str = Some("Line 3".to_string());
+        editor_with_history(state_dir.path())
+            .handle_request(input)
+            .unwrap();
+        verify_file_content(file.path(), "First\nLine 2\nLine 3");
+
+        // Each undo uses a fresh editor, as separate CLI invocations would
+        let input = create_test_input("undo_edit", path);
+        let result = editor_with_history(state_dir.path())
+            .handle_request(input)
+            .unwrap();
+        assert_success_contains(&result, "undone successfully");
+        assert_success_contains(&result, "     2\tLine 2");
+        verify_file_content(file.path(), "First\nLine 2");
+
+        let input = create_test_input("undo_edit", path);
+        editor_with_history(state_dir.path())
+            .handle_request(input)
+            .unwrap();
+        verify_file_content(file.path(), "Line 1\nLine 2");
+
+        let input = create_test_input("undo_edit", path);
+        let result = editor_with_history(state_dir.path()).handle_request(input);
+        assert!(matches!(result, Err(EditorError::NoEditHistory(_))));
+    }
+
+    #[test]
+    fn test_undo_create_removes_file() {
+        let state_dir = tempdir().unwrap();
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("new_file.txt");
+        let editor = editor_with_history(state_dir.path());
+
+        let mut input = create_test_input("create", file_path.to_str().unwrap());
+        input.input.file_text = Some("Content".to_string());
+        editor.handle_request(input).unwrap();
+        assert!(file_path.exists());
+
+        let input = create_test_input("undo_edit", file_path.to_str().unwrap());
+        editor.handle_request(input).unwrap();
+        assert!(!file_path.exists());
+    }
+
+    #[test]
+    fn test_failed_edit_is_not_recorded() {
+        let state_dir = tempdir().unwrap();
+        let file = create_test_file("Original content");
+        let path = file.path().to_str().unwrap();
+        let editor = editor_with_history(state_dir.path());
+
+        let mut input = create_test_input("str_replace", path);
+        input.input.old_str = Some("Missing".to_string());
+        assert!(editor.handle_request(input).is_err());
+
+        let input = create_test_input("undo_edit", path);
+        let result = editor.handle_request(input);
+        assert!(matches!(result, Err(EditorError::NoEditHistory(_))));
+    }
+
+    #[test]
+    fn test_history_keeps_latest_entries() {
+        let state_dir = tempdir().unwrap();
+        let history = History::new(state_dir.path());
+        let path = Path::new("/some/file.txt");
+
+        for i in 0..crate::history::MAX_ENTRIES + 5 {
+            history.record(path, Some(&i.to_string())).unwrap();
+        }
+
+        for i in (5..crate::history::MAX_ENTRIES + 5).rev() {
+            assert_eq!(history.pop(path).unwrap(), Some(Some(i.to_string())));
+        }
+        assert_eq!(history.pop(path).unwrap(), None);
+    }
+
+    #[test]
+    fn test_history_failure_does_not_fail_edit() {
+        let state_file = create_test_file("");
+        let file = create_test_file("hello");
+        let path = file.path().to_str().unwrap();
+
+        let mut input = create_test_input("str_replace", path);
+        input.input.old_str = Some("hello".to_string());
+        input.input.new_str = Some("bye".to_string());
+        let result = editor_with_history(state_file.path())
+            .handle_request(input)
+            .unwrap();
+
+        assert_success_contains(&result, "has been edited");
+        assert_success_contains(&result, "Warning: the changes were written");
+        verify_file_content(file.path(), "bye");
+    }
+
+    #[test]
+    fn test_failed_undo_keeps_history() {
+        let state_dir = tempdir().unwrap();
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("file.txt");
+        let path = file_path.to_str().unwrap();
+        let editor = editor_with_history(state_dir.path());
+        fs::write(&file_path, "Original\n").unwrap();
+
+        let mut input = create_test_input("str_replace", path);
+        input.input.old_str = Some("Original".to_string());
+        input.input.new_str = Some("Edited".to_string());
+        editor.handle_request(input).unwrap();
+
+        // A directory in the file's place makes restoring it fail
+        fs::remove_file(&file_path).unwrap();
+        fs::create_dir(&file_path).unwrap();
+        let input = create_test_input("undo_edit", path);
+        assert!(editor.handle_request(input).is_err());
+
+        fs::remove_dir(&file_path).unwrap();
+        fs::write(&file_path, "Edited\n").unwrap();
+        let input = create_test_input("undo_edit", path);
+        editor.handle_request(input).unwrap();
+        assert_eq!(fs::read_to_string(&file_path).unwrap(), "Original\n");
     }
 }
 
@@ -388,9 +573,9 @@ mod validation_tests {
 
         // Test just the invalid command handling directly
         let cmd_result = Command::from_str("invalid_command");
-        assert!(matches!(cmd_result, Err(EditorError::UnknownCommand(_))));
+        assert!(matches!(cmd_result, Err(EditorError::UnknownCommand(_, _))));
 
-        if let Err(EditorError::UnknownCommand(cmd)) = cmd_result {
+        if let Err(EditorError::UnknownCommand(cmd, _)) = cmd_result {
             assert_eq!(cmd, "invalid_command");
         }
     }
@@ -420,7 +605,7 @@ mod validation_tests {
         let dir = tempdir().unwrap();
         assert!(matches!(
             validate_path(dir.path(), &Command::StrReplace),
-            Err(EditorError::InvalidRange(_))
+            Err(EditorError::IsADirectory(_, Command::StrReplace))
         ));
     }
 }
@@ -515,3 +700,1830 @@ mod create_tests {
         assert!(matches!(result, Err(EditorError::MissingFileText)));
     }
 }
+
+mod stream_tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_request_unknown_command() {
+        let result = parse_request(
+            r#"{"input":{"command":"invalid_command","path":"/tmp"}}"#,
+            ToolVersion::default(),
+        );
+        assert!(matches!(result, Err(EditorError::UnknownCommand(_, _))));
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Unrecognized command invalid_command. The allowed commands for the str_replace_editor tool are: view, create, str_replace, insert, delete, undo_edit, multi_edit, transaction, apply_patch, rename, copy, remove, mkdir, replace_lines, search, find"
+        );
+    }
+
+    #[test]
+    fn test_parse_request_invalid_json() {
+        let result = parse_request("{not json", ToolVersion::default());
+        assert!(matches!(result, Err(EditorError::InvalidRequest(_))));
+    }
+
+    #[test]
+    fn test_stream_multiple_requests() {
+        let file = create_test_file("Line 1\nLine 2");
+        let path = file.path().to_str().unwrap();
+
+        let input = format!(
+            "{}\n\n{}\nnot json\n",
+            serde_json::json!({"input": {"command": "view", "path": path}}),
+            serde_json::json!({"input": {"command": "insert", "path": path, "insert_line": 2, "new_str": "Line 3"}}),
+        );
+        let mut output = Vec::new();
+        crate::run_stream(
+            &Editor::default(),
+            crate::OutputFormat::CliResult,
+            input.as_bytes(),
+            &mut output,
+        )
+        .unwrap();
+
+        let results: Vec<serde_json::Value> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(results.len(), 3);
+        assert!(results[0]["is_error"].is_null());
+        assert!(results[1]["is_error"].is_null());
+        assert_eq!(results[2]["is_error"], true);
+        verify_file_content(file.path(), "Line 1\nLine 2\nLine 3");
+    }
+
+    #[test]
+    fn test_stream_continues_after_invalid_utf8() {
+        let file = create_test_file("Line 1");
+        let request = serde_json::json!({"input": {"command": "view", "path": file.path()}});
+
+        let mut input = b"{\"input\": \xff}\n".to_vec();
+        input.extend_from_slice(format!("{}\n", request).as_bytes());
+        let mut output = Vec::new();
+        crate::run_stream(
+            &Editor::default(),
+            crate::OutputFormat::CliResult,
+            input.as_slice(),
+            &mut output,
+        )
+        .unwrap();
+
+        let results: Vec<serde_json::Value> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0]["is_error"], true);
+        assert_success_contains(results[1]["content"].as_str().unwrap(), "Line 1");
+    }
+}
+
+mod tool_use_tests {
+    use super::*;
+    use crate::OutputFormat;
+
+    #[test]
+    fn test_parse_tool_use_block() {
+        let request = parse_request(
+            r#"{"type":"tool_use","id":"toolu_01","name":"str_replace_editor","input":{"command":"view","path":"/tmp"}}"#,
+            ToolVersion::default(),
+        )
+        .unwrap();
+        assert_eq!(request.id.as_deref(), Some("toolu_01"));
+        assert_eq!(request.input.command, Command::View);
+    }
+
+    #[test]
+    fn test_tool_result_output() {
+        let file = create_test_file("File content");
+        let request = serde_json::json!({
+            "type": "tool_use",
+            "id": "toolu_01",
+            "name": "str_replace_editor",
+            "input": {"command": "view", "path": file.path()},
+        });
+
+        let output = crate::run_request(
+            &Editor::default(),
+            OutputFormat::ToolResult,
+            &request.to_string(),
+        )
+        .unwrap();
+        let result: serde_json::Value = serde_json::from_str(&output).unwrap();
+
+        assert_eq!(result["type"], "tool_result");
+        assert_eq!(result["tool_use_id"], "toolu_01");
+        assert_eq!(result["is_error"], false);
+        assert_success_contains(result["content"].as_str().unwrap(), "File content");
+    }
+
+    #[test]
+    fn test_tool_result_error_keeps_id() {
+        let request = r#"{"type":"tool_use","id":"toolu_02","name":"str_replace_editor","input":{"command":"bogus","path":"/tmp"}}"#;
+
+        let output =
+            crate::run_request(&Editor::default(), OutputFormat::ToolResult, request).unwrap();
+        let result: serde_json::Value = serde_json::from_str(&output).unwrap();
+
+        assert_eq!(result["tool_use_id"], "toolu_02");
+        assert_eq!(result["is_error"], true);
+        assert_success_contains(
+            result["content"].as_str().unwrap(),
+            "Unrecognized command bogus",
+        );
+    }
+}
+
+mod tool_version_tests {
+    use super::*;
+
+    #[test]
+    fn test_newer_versions_reject_undo_edit() {
+        let result = parse_request(
+            r#"{"tool_version":"text_editor_20250728","input":{"command":"undo_edit","path":"/tmp"}}"#,
+            ToolVersion::default(),
+        );
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Unrecognized command undo_edit. The allowed commands for the str_replace_based_edit_tool tool are: view, create, str_replace, insert, delete, multi_edit, transaction, apply_patch, rename, copy, remove, mkdir, replace_lines, search, find"
+        );
+
+        let file = create_test_file("Content");
+        let input = create_test_input("undo_edit", file.path().to_str().unwrap());
+        let editor = Editor {
+            tool_version: ToolVersion::TextEditor20250429,
+            ..Default::default()
+        };
+        let result = editor.handle_request(input);
+        assert!(matches!(result, Err(EditorError::UnknownCommand(_, _))));
+    }
+
+    #[test]
+    fn test_view_max_characters() {
+        let file = create_test_file("Line 1\nLine 2\nLine 3");
+        let editor = Editor {
+            tool_version: ToolVersion::TextEditor20250728,
+            ..Default::default()
+        };
+
+        let mut input = create_test_input("view", file.path().to_str().unwrap());
+        input.input.max_characters = Some(10);
+        let result = editor.handle_request(input).unwrap();
+
+        assert_success_contains(&result, "     1\tLin");
+        assert_success_contains(&result, "<response clipped>");
+        assert!(!result.contains("Line 2"));
+    }
+
+    #[test]
+    fn test_max_characters_requires_20250728() {
+        let file = create_test_file("Content");
+
+        let mut input = create_test_input("view", file.path().to_str().unwrap());
+        input.input.max_characters = Some(10);
+        let result = handle_command(input.input);
+
+        assert!(matches!(
+            result,
+            Err(EditorError::UnsupportedParameter("max_characters", _))
+        ));
+    }
+}
+
+mod root_tests {
+    use super::*;
+
+    fn editor_with_root(root: &Path) -> Editor {
+        Editor {
+            root: Some(root.canonicalize().unwrap()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_paths_inside_root() {
+        let root = tempdir().unwrap();
+        let editor = editor_with_root(root.path());
+        let file_path = root.path().join("nested/dirs/new_file.txt");
+
+        let mut input = create_test_input("create", file_path.to_str().unwrap());
+        input.input.file_text = Some("Content".to_string());
+        editor.handle_request(input).unwrap();
+
+        let input = create_test_input("view", root.path().to_str().unwrap());
+        let result = editor.handle_request(input).unwrap();
+        assert_success_contains(&result, "nested");
+    }
+
+    #[test]
+    fn test_paths_outside_root() {
+        let root = tempdir().unwrap();
+        let outside = create_test_file("Secret");
+        let editor = editor_with_root(root.path());
+
+        let input = create_test_input("view", outside.path().to_str().unwrap());
+        let result = editor.handle_request(input);
+        assert!(matches!(result, Err(EditorError::OutsideRoot(_, _))));
+
+        // `..` components cannot climb out of the root
+        let escaping = root.path().join("../escaped/new_file.txt");
+        let mut input = create_test_input("create", escaping.to_str().unwrap());
+        input.input.file_text = Some("Content".to_string());
+        let result = editor.handle_request(input);
+        assert!(matches!(result, Err(EditorError::OutsideRoot(_, _))));
+        assert!(!root.path().join("../escaped").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinks_cannot_escape_root() {
+        let root = tempdir().unwrap();
+        let outside = tempdir().unwrap();
+        let editor = editor_with_root(root.path());
+
+        std::os::unix::fs::symlink(outside.path(), root.path().join("link")).unwrap();
+        let through_link = root.path().join("link/sub/new_file.txt");
+        let mut input = create_test_input("create", through_link.to_str().unwrap());
+        input.input.file_text = Some("Content".to_string());
+        let result = editor.handle_request(input);
+        assert!(matches!(result, Err(EditorError::OutsideRoot(_, _))));
+        assert!(!outside.path().join("sub").exists());
+
+        // A dangling symlink would otherwise let `create` write outside
+        let target = outside.path().join("target.txt");
+        std::os::unix::fs::symlink(&target, root.path().join("dangling")).unwrap();
+        let mut input = create_test_input("create", root.path().join("dangling").to_str().unwrap());
+        input.input.file_text = Some("Content".to_string());
+        let result = editor.handle_request(input);
+        assert!(matches!(result, Err(EditorError::OutsideRoot(_, _))));
+        assert!(!target.exists());
+    }
+
+    #[test]
+    fn test_patch_cannot_probe_outside_root() {
+        let parent = tempdir().unwrap();
+        let root = parent.path().join("root");
+        fs::create_dir(&root).unwrap();
+        fs::write(parent.path().join("outside.txt"), "secret\n").unwrap();
+        let editor = editor_with_root(&root);
+
+        // Whether or not the hunk would apply, the file is never read
+        for guess in ["wrong", "secret"] {
+            let mut input = create_test_input("apply_patch", root.to_str().unwrap());
+            input.input.patch = Some(format!(
+                "--- a/../outside.txt\n+++ b/../outside.txt\n@@ -1 +1 @@\n-{}\n+changed\n",
+                guess
+            ));
+            let result = editor.handle_request(input);
+            assert!(matches!(result, Err(EditorError::OutsideRoot(_, _))));
+        }
+        assert_eq!(
+            fs::read_to_string(parent.path().join("outside.txt")).unwrap(),
+            "secret\n"
+        );
+    }
+
+    #[test]
+    fn test_copy_cannot_probe_outside_root() {
+        let parent = tempdir().unwrap();
+        let root = parent.path().join("root");
+        fs::create_dir(&root).unwrap();
+        fs::write(root.join("a.txt"), "a").unwrap();
+        fs::write(parent.path().join("outside.txt"), "secret").unwrap();
+        let editor = editor_with_root(&root);
+
+        // An existing and a missing destination fail the same way
+        for name in ["outside.txt", "missing.txt"] {
+            let mut input = create_test_input("copy", root.join("a.txt").to_str().unwrap());
+            input.input.new_path = Some(parent.path().join(name).to_string_lossy().to_string());
+            let result = editor.handle_request(input);
+            assert!(matches!(result, Err(EditorError::OutsideRoot(_, _))));
+        }
+        assert!(!parent.path().join("missing.txt").exists());
+    }
+}
+
+mod read_only_tests {
+    use super::*;
+
+    fn read_only_editor() -> Editor {
+        Editor {
+            read_only: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_read_only_allows_view() {
+        let file = create_test_file("File content");
+
+        let input = create_test_input("view", file.path().to_str().unwrap());
+        let result = read_only_editor().handle_request(input).unwrap();
+        assert_success_contains(&result, "File content");
+    }
+
+    #[test]
+    fn test_read_only_rejects_mutations() {
+        let file = create_test_file("Line 1\nLine 2");
+        let path = file.path().to_str().unwrap();
+        let dir = tempdir().unwrap();
+        let new_path = dir.path().join("new_file.txt");
+
+        let mut create = create_test_input("create", new_path.to_str().unwrap());
+        create.input.file_text = Some("Content".to_string());
+        let mut str_replace = create_test_input("str_replace", path);
+        str_replace.input.old_str = Some("Line 1".to_string());
+        let mut insert = create_test_input("insert", path);
+        insert.input.insert_line = Some(0);
+        insert.input.new_str = Some("New".to_string());
+        let mut delete = create_test_input("delete", path);
+        delete.input.delete_range = Some(vec![1, 1]);
+        let undo = create_test_input("undo_edit", path);
+
+        for input in [create, str_replace, insert, delete, undo] {
+            let command = input.input.command.clone();
+            let result = read_only_editor().handle_request(input);
+            assert!(
+                matches!(result, Err(EditorError::ReadOnly(ref c)) if *c == command),
+                "{} was not rejected: {:?}",
+                command,
+                result
+            );
+        }
+
+        verify_file_content(file.path(), "Line 1\nLine 2");
+        assert!(!new_path.exists());
+    }
+}
+
+#[cfg(unix)]
+mod atomic_write_tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn mode(path: &Path) -> u32 {
+        fs::metadata(path).unwrap().permissions().mode() & 0o7777
+    }
+
+    #[test]
+    fn test_edit_preserves_permissions() {
+        let file = create_test_file("Original content");
+        let path = file.path();
+        fs::set_permissions(path, fs::Permissions::from_mode(0o640)).unwrap();
+
+        let mut input = create_test_input("str_replace", path.to_str().unwrap());
+        input.input.old_str = Some("Original".to_string());
+        input.input.new_str = Some("New".to_string());
+        handle_command(input.input).unwrap();
+
+        verify_file_content(path, "New content");
+        assert_eq!(mode(path), 0o640);
+    }
+
+    #[test]
+    fn test_edit_preserves_executable_bit() {
+        let file = create_test_file("#!/bin/sh\necho hello");
+        let path = file.path();
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let mut input = create_test_input("insert", path.to_str().unwrap());
+        input.input.insert_line = Some(1);
+        input.input.new_str = Some("set -e".to_string());
+        handle_command(input.input).unwrap();
+
+        let mut input = create_test_input("delete", path.to_str().unwrap());
+        input.input.delete_range = Some(vec![3, 3]);
+        handle_command(input.input).unwrap();
+
+        verify_file_content(path, "#!/bin/sh\nset -e");
+        assert_eq!(mode(path), 0o755);
+    }
+
+    #[test]
+    fn test_create_uses_default_permissions() {
+        let dir = tempdir().unwrap();
+        let reference = dir.path().join("reference.txt");
+        File::create(&reference).unwrap();
+        let file_path = dir.path().join("new_file.txt");
+
+        let mut input = create_test_input("create", file_path.to_str().unwrap());
+        input.input.file_text = Some("Content".to_string());
+        handle_command(input.input).unwrap();
+
+        assert_eq!(mode(&file_path), mode(&reference));
+        // No temporary files are left behind
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn test_edit_through_symlink_keeps_link() {
+        let dir = tempdir().unwrap();
+        let target = dir.path().join("target.txt");
+        fs::write(&target, "Original content\n").unwrap();
+        let link = dir.path().join("link.txt");
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        let mut input = create_test_input("str_replace", link.to_str().unwrap());
+        input.input.old_str = Some("Original".to_string());
+        input.input.new_str = Some("New".to_string());
+        handle_command(input.input).unwrap();
+
+        assert!(link.is_symlink());
+        verify_file_content(&target, "New content");
+    }
+}
+
+mod line_ending_tests {
+    use super::*;
+
+    // Write exact content, without the trailing newline `create_test_file` adds
+    fn create_exact_file(content: &str) -> NamedTempFile {
+        let file = NamedTempFile::new().unwrap();
+        fs::write(file.path(), content).unwrap();
+        file
+    }
+
+    fn insert_at(path: &Path, insert_line: i32, new_str: &str) {
+        let mut input = create_test_input("insert", path.to_str().unwrap());
+        input.input.insert_line = Some(insert_line);
+        input.input.new_str = Some(new_str.to_string());
+        handle_command(input.input).unwrap();
+    }
+
+    fn delete_lines(path: &Path, start: i32, end: i32) {
+        let mut input = create_test_input("delete", path.to_str().unwrap());
+        input.input.delete_range = Some(vec![start, end]);
+        handle_command(input.input).unwrap();
+    }
+
+    #[test]
+    fn test_insert_preserves_line_endings() {
+        let cases = [
+            ("a\nb\n", 1, "a\nX\nb\n"),
+            ("a\nb", 2, "a\nb\nX"),
+            ("a\r\nb\r\n", 1, "a\r\nX\r\nb\r\n"),
+            ("a\r\nb", 2, "a\r\nb\r\nX"),
+            ("a\r\nb\r\nc\n", 3, "a\r\nb\r\nc\nX\r\n"),
+            ("", 0, "X\n"),
+        ];
+
+        for (original, insert_line, expected) in cases {
+            let file = create_exact_file(original);
+            insert_at(file.path(), insert_line, "X");
+            assert_eq!(
+                fs::read_to_string(file.path()).unwrap(),
+                expected,
+                "inserting into {:?}",
+                original
+            );
+        }
+    }
+
+    #[test]
+    fn test_insert_multiline_into_crlf() {
+        let file = create_exact_file("a\r\nb\r\n");
+        insert_at(file.path(), 1, "X\nY");
+        assert_eq!(
+            fs::read_to_string(file.path()).unwrap(),
+            "a\r\nX\r\nY\r\nb\r\n"
+        );
+    }
+
+    #[test]
+    fn test_delete_preserves_line_endings() {
+        let cases = [
+            ("a\nb\nc\n", 2, 2, "a\nc\n"),
+            ("a\nb\nc", 3, 3, "a\nb"),
+            ("a\r\nb\r\nc\r\n", 1, 1, "b\r\nc\r\n"),
+            ("a\r\nb\r\nc", 2, 3, "a"),
+            ("a\r\nb\nc\r\n", 2, 2, "a\r\nc\r\n"),
+            ("a\nb\n", 1, 2, ""),
+        ];
+
+        for (original, start, end, expected) in cases {
+            let file = create_exact_file(original);
+            delete_lines(file.path(), start, end);
+            assert_eq!(
+                fs::read_to_string(file.path()).unwrap(),
+                expected,
+                "deleting [{}, {}] from {:?}",
+                start,
+                end,
+                original
+            );
+        }
+    }
+
+    #[test]
+    fn test_str_replace_in_crlf_file() {
+        let file = create_exact_file("fn main() {\r\n    old();\r\n}");
+
+        let mut input = create_test_input("str_replace", file.path().to_str().unwrap());
+        input.input.old_str = Some("{\n    old();".to_string());
+        input.input.new_str = Some("{\n    new();\n    more();".to_string());
+        handle_command(input.input).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(file.path()).unwrap(),
+            "fn main() {\r\n    new();\r\n    more();\r\n}"
+        );
+    }
+}
+
+mod diff_tests {
+    use super::*;
+
+    #[test]
+    fn test_no_diff_by_default() {
+        let file = create_test_file("Original content");
+
+        let mut input = create_test_input("str_replace", file.path().to_str().unwrap());
+        input.input.old_str = Some("Original".to_string());
+        input.input.new_str = Some("New".to_string());
+        let result = handle_command(input.input).unwrap();
+
+        assert!(!result.contains("@@"));
+    }
+
+    #[test]
+    fn test_str_replace_diff() {
+        let file = create_test_file("Line 1\nLine 2\nLine 3\nLine 4\nLine 5\nLine 6");
+        let path = file.path().to_str().unwrap();
+
+        let mut input = create_test_input("str_replace", path);
+        input.input.old_str = Some("Line 4".to_string());
+        input.input.new_str = Some("Changed".to_string());
+        input.input.diff = Some(true);
+        input.input.diff_context = Some(1);
+        let result = handle_command(input.input).unwrap();
+
+        assert_success_contains(&result, &format!("--- {}\n+++ {}\n", path, path));
+        assert_success_contains(
+            &result,
+            "@@ -3,3 +3,3 @@\n Line 3\n-Line 4\n+Changed\n Line 5\n",
+        );
+        assert!(!result.contains(" Line 2"));
+    }
+
+    #[test]
+    fn test_create_diff_from_editor_default() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("new_file.txt");
+        let editor = Editor {
+            diff: true,
+            ..Default::default()
+        };
+
+        let mut input = create_test_input("create", file_path.to_str().unwrap());
+        input.input.file_text = Some("Hello\nworld\n".to_string());
+        let result = editor.handle_request(input).unwrap();
+
+        assert_success_contains(&result, "--- /dev/null\n");
+        assert_success_contains(&result, "@@ -0,0 +1,2 @@\n+Hello\n+world\n");
+
+        // A request can still opt out
+        let mut input = create_test_input("delete", file_path.to_str().unwrap());
+        input.input.delete_range = Some(vec![1, 1]);
+        input.input.diff = Some(false);
+        let result = editor.handle_request(input).unwrap();
+        assert!(!result.contains("@@"));
+    }
+}
+
+mod snippet_tests {
+    use super::*;
+
+    fn numbered_file(count: usize) -> NamedTempFile {
+        let content: Vec<_> = (1..=count).map(|i| format!("Line {}", i)).collect();
+        create_test_file(&content.join("\n"))
+    }
+
+    #[test]
+    fn test_str_replace_snippet() {
+        let file = numbered_file(20);
+
+        let mut input = create_test_input("str_replace", file.path().to_str().unwrap());
+        input.input.old_str = Some("Line 10\n".to_string());
+        input.input.new_str = Some("Line 10\n    Added\n".to_string());
+        let result = handle_command(input.input).unwrap();
+
+        assert_success_contains(&result, "Replaced 1 occurrences");
+        assert_success_contains(
+            &result,
+            "     6\tLine 6\n     7\tLine 7\n     8\tLine 8\n     9\tLine 9\n    10\tLine 10\n    11\t    Added\n    12\tLine 11\n    13\tLine 12\n    14\tLine 13\n    15\tLine 14\n",
+        );
+        assert!(!result.contains("Line 5\n"));
+        assert!(!result.contains("Line 15"));
+    }
+
+    #[test]
+    fn test_str_replace_multi_snippets() {
+        let file = numbered_file(30);
+
+        let mut input = create_test_input("str_replace", file.path().to_str().unwrap());
+        input.input.old_str = Some(r"(?m)Line (2|3|25)$".to_string());
+        input.input.new_str = Some("Changed $1".to_string());
+        input.input.use_regex = Some(true);
+        input.input.allow_multi = Some(true);
+        input.input.snippet_lines = Some(1);
+        let result = handle_command(input.input).unwrap();
+
+        // Nearby sites share a snippet, distant ones get their own
+        assert_eq!(
+            result
+                .matches("Here's the result of running `cat -n`")
+                .count(),
+            2
+        );
+        assert_success_contains(
+            &result,
+            "     1\tLine 1\n     2\tChanged 2\n     3\tChanged 3\n     4\tLine 4\n",
+        );
+        assert_success_contains(
+            &result,
+            "    24\tLine 24\n    25\tChanged 25\n    26\tLine 26\n",
+        );
+    }
+
+    #[test]
+    fn test_delete_snippet() {
+        let file = numbered_file(10);
+
+        let mut input = create_test_input("delete", file.path().to_str().unwrap());
+        input.input.delete_range = Some(vec![5, 6]);
+        input.input.snippet_lines = Some(2);
+        let result = handle_command(input.input).unwrap();
+
+        assert_success_contains(
+            &result,
+            "     3\tLine 3\n     4\tLine 4\n     5\tLine 7\n     6\tLine 8\n",
+        );
+        assert!(!result.contains("Line 9"));
+    }
+
+    #[test]
+    fn test_insert_snippet_shows_all_new_lines() {
+        let file = numbered_file(10);
+
+        let mut input = create_test_input("insert", file.path().to_str().unwrap());
+        input.input.insert_line = Some(5);
+        input.input.new_str = Some("A\nB\nC\nD\nE\nF".to_string());
+        input.input.snippet_lines = Some(1);
+        let result = handle_command(input.input).unwrap();
+
+        assert_success_contains(
+            &result,
+            "     5\tLine 5\n     6\tA\n     7\tB\n     8\tC\n     9\tD\n    10\tE\n    11\tF\n    12\tLine 6\n",
+        );
+        assert!(!result.contains("Line 4"));
+    }
+}
+
+mod dry_run_tests {
+    use super::*;
+    use crate::history::History;
+
+    #[test]
+    fn test_dry_run_str_replace() {
+        let file = create_test_file("Original content");
+
+        let mut input = create_test_input("str_replace", file.path().to_str().unwrap());
+        input.input.old_str = Some("Original".to_string());
+        input.input.new_str = Some("New".to_string());
+        input.input.dry_run = Some(true);
+        let result = handle_command(input.input).unwrap();
+
+        assert_success_contains(&result, "no changes were written");
+        assert_success_contains(&result, "     1\tNew content");
+        assert_success_contains(&result, "-Original content\n+New content\n");
+        verify_file_content(file.path(), "Original content");
+    }
+
+    #[test]
+    fn test_dry_run_create_makes_nothing() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("nested/new_file.txt");
+        let editor = Editor {
+            dry_run: true,
+            ..Default::default()
+        };
+
+        let mut input = create_test_input("create", file_path.to_str().unwrap());
+        input.input.file_text = Some("Content".to_string());
+        let result = editor.handle_request(input).unwrap();
+
+        assert_success_contains(&result, "+Content");
+        assert!(!dir.path().join("nested").exists());
+    }
+
+    #[test]
+    fn test_dry_run_replay_matches_preview() {
+        let state_dir = tempdir().unwrap();
+        let file = create_test_file("Line 1\nLine 2\nLine 3");
+        let path = file.path().to_str().unwrap();
+        let editor = Editor {
+            history: Some(History::new(state_dir.path())),
+            ..Default::default()
+        };
+
+        let mut preview = create_test_input("delete", path);
+        preview.input.delete_range = Some(vec![2, 2]);
+        preview.input.dry_run = Some(true);
+        preview.input.diff = Some(true);
+        let preview_result = editor.handle_request(preview).unwrap();
+        verify_file_content(file.path(), "Line 1\nLine 2\nLine 3");
+
+        // A dry run is not recorded in the edit history
+        let undo = create_test_input("undo_edit", path);
+        assert!(matches!(
+            editor.handle_request(undo),
+            Err(EditorError::NoEditHistory(_))
+        ));
+
+        let mut replay = create_test_input("delete", path);
+        replay.input.delete_range = Some(vec![2, 2]);
+        replay.input.diff = Some(true);
+        let replay_result = editor.handle_request(replay).unwrap();
+
+        assert!(preview_result.ends_with(&replay_result));
+        verify_file_content(file.path(), "Line 1\nLine 3");
+    }
+}
+
+mod expected_hash_tests {
+    use super::*;
+
+    fn viewed_hash(path: &Path) -> String {
+        let input = create_test_input("view", path.to_str().unwrap());
+        let result = handle_command(input.input).unwrap();
+        result
+            .lines()
+            .find_map(|line| line.strip_prefix("Content hash: "))
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn test_edit_with_current_hash() {
+        let file = create_test_file("Original content");
+        let hash = viewed_hash(file.path());
+
+        let mut input = create_test_input("str_replace", file.path().to_str().unwrap());
+        input.input.old_str = Some("Original".to_string());
+        input.input.new_str = Some("New".to_string());
+        input.input.expected_hash = Some(hash);
+        let result = handle_command(input.input).unwrap();
+
+        verify_file_content(file.path(), "New content");
+        // The new hash is returned so further edits can be guarded too
+        assert_success_contains(
+            &result,
+            &format!("Content hash: {}", viewed_hash(file.path())),
+        );
+    }
+
+    #[test]
+    fn test_edit_rejected_after_concurrent_change() {
+        let file = create_test_file("Line 1\nLine 2");
+        let hash = viewed_hash(file.path());
+
+        // Someone else edits the file after the model viewed it
+        fs::write(file.path(), "Line 1\nLine 2 changed\n").unwrap();
+
+        let mut input = create_test_input("insert", file.path().to_str().unwrap());
+        input.input.insert_line = Some(0);
+        input.input.new_str = Some("Header".to_string());
+        input.input.expected_hash = Some(hash);
+        let result = handle_command(input.input);
+
+        assert!(matches!(result, Err(EditorError::FileChanged(_))));
+        verify_file_content(file.path(), "Line 1\nLine 2 changed");
+    }
+
+    #[test]
+    fn test_view_range_reports_whole_file_hash() {
+        let file = create_test_file("Line 1\nLine 2\nLine 3");
+
+        let mut input = create_test_input("view", file.path().to_str().unwrap());
+        input.input.view_range = Some(vec![1, 1]);
+        let result = handle_command(input.input).unwrap();
+
+        assert_success_contains(
+            &result,
+            &format!("Content hash: {}", viewed_hash(file.path())),
+        );
+    }
+}
+
+mod multi_edit_tests {
+    use super::*;
+
+    fn edit(old_str: &str, new_str: &str) -> EditOperation {
+        EditOperation {
+            old_str: old_str.to_string(),
+            new_str: Some(new_str.to_string()),
+            allow_multi: None,
+            use_regex: None,
+            match_mode: None,
+            occurrence: None,
+            line_range: None,
+        }
+    }
+
+    #[test]
+    fn test_multi_edit_applies_sequentially() {
+        let file = create_test_file("fn old_name() {\n    let x = 1;\n    let y = 2;\n}");
+
+        let mut input = create_test_input("multi_edit", file.path().to_str().unwrap());
+        input.input.edits = Some(vec![
+            edit("old_name", "new_name"),
+            // Sees the result of the first edit
+            edit("fn new_name()", "pub fn new_name()"),
+            EditOperation {
+                use_regex: Some(true),
+                allow_multi: Some(true),
+                ..edit(r"let (\w) = (\d);", "let $1 = $2 * 10;")
+            },
+        ]);
+        let result = handle_command(input.input).unwrap();
+
+        assert_success_contains(&result, "Applied 3 edits (4 replacements)");
+        assert_success_contains(&result, "     1\tpub fn new_name() {");
+        verify_file_content(
+            file.path(),
+            "pub fn new_name() {\n    let x = 1 * 10;\n    let y = 2 * 10;\n}",
+        );
+    }
+
+    #[test]
+    fn test_multi_edit_is_all_or_nothing() {
+        let file = create_test_file("alpha\nbeta\ngamma");
+
+        let mut input = create_test_input("multi_edit", file.path().to_str().unwrap());
+        input.input.edits = Some(vec![
+            edit("alpha", "ALPHA"),
+            edit("beta", "BETA"),
+            edit("delta", "DELTA"),
+        ]);
+        let result = handle_command(input.input);
+
+        match result {
+            Err(err @ EditorError::MultiEdit(2, _)) => {
+                assert_success_contains(
+                    &err.to_string(),
+                    "edits[2] failed: The string was not found",
+                );
+            }
+            other => panic!("expected edits[2] to fail, got {:?}", other),
+        }
+        verify_file_content(file.path(), "alpha\nbeta\ngamma");
+    }
+
+    #[test]
+    fn test_multi_edit_missing_edits() {
+        let file = create_test_file("content");
+
+        let mut input = create_test_input("multi_edit", file.path().to_str().unwrap());
+        input.input.edits = Some(vec![]);
+        let result = handle_command(input.input);
+
+        assert!(matches!(result, Err(EditorError::MissingEdits)));
+    }
+}
+
+mod transaction_tests {
+    use super::*;
+
+    fn run_transaction(operations: serde_json::Value) -> Result<String, EditorError> {
+        let request = serde_json::json!({
+            "input": { "command": "transaction", "operations": operations }
+        });
+        let request = parse_request(&request.to_string(), ToolVersion::default())?;
+        Editor::default().handle_request(request)
+    }
+
+    #[test]
+    fn test_transaction_across_files() {
+        let dir = tempdir().unwrap();
+        let existing = dir.path().join("lib.rs");
+        fs::write(&existing, "mod old;\n").unwrap();
+        let created = dir.path().join("new.rs");
+
+        let result = run_transaction(serde_json::json!([
+            { "command": "create", "path": created, "file_text": "pub fn f() {}\n" },
+            // Sees the file created by the previous operation
+            { "command": "str_replace", "path": created, "old_str": "f()", "new_str": "g()" },
+            { "command": "str_replace", "path": existing, "old_str": "mod old;", "new_str": "mod new;" },
+        ]))
+        .unwrap();
+
+        assert_success_contains(&result, "Applied 3 operations to 2 files");
+        assert_eq!(fs::read_to_string(&created).unwrap(), "pub fn g() {}\n");
+        assert_eq!(fs::read_to_string(&existing).unwrap(), "mod new;\n");
+    }
+
+    #[test]
+    fn test_transaction_failure_writes_nothing() {
+        let dir = tempdir().unwrap();
+        let existing = dir.path().join("lib.rs");
+        fs::write(&existing, "mod old;\n").unwrap();
+        let created = dir.path().join("new.rs");
+
+        let result = run_transaction(serde_json::json!([
+            { "command": "create", "path": created, "file_text": "pub fn f() {}\n" },
+            { "command": "str_replace", "path": existing, "old_str": "mod old;", "new_str": "mod new;" },
+            { "command": "str_replace", "path": existing, "old_str": "missing", "new_str": "x" },
+        ]));
+
+        match result {
+            Err(err @ EditorError::Transaction(2, _)) => {
+                assert_success_contains(
+                    &err.to_string(),
+                    "operations[2] failed: The string was not found",
+                );
+            }
+            other => panic!("expected operations[2] to fail, got {:?}", other),
+        }
+        assert!(!created.exists());
+        assert_eq!(fs::read_to_string(&existing).unwrap(), "mod old;\n");
+    }
+
+    #[test]
+    fn test_transaction_rolls_back_failed_commit() {
+        let dir = tempdir().unwrap();
+        let existing = dir.path().join("lib.rs");
+        fs::write(&existing, "mod old;\n").unwrap();
+        // A regular file cannot become a parent directory, which is only
+        // discovered when the change is written
+        let blocked = dir.path().join("lib.rs").join("child.rs");
+
+        let result = run_transaction(serde_json::json!([
+            { "command": "str_replace", "path": existing, "old_str": "mod old;", "new_str": "mod new;" },
+            { "command": "create", "path": blocked, "file_text": "content\n" },
+        ]));
+
+        assert!(matches!(result, Err(EditorError::CommitFailed(..))));
+        assert_eq!(fs::read_to_string(&existing).unwrap(), "mod old;\n");
+    }
+}
+
+mod apply_patch_tests {
+    use super::*;
+
+    fn patch_input(path: &Path, patch: &str) -> Input {
+        let mut input = create_test_input("apply_patch", path.to_str().unwrap()).input;
+        input.patch = Some(patch.to_string());
+        input
+    }
+
+    #[test]
+    fn test_apply_multi_file_patch() {
+        let dir = tempdir().unwrap();
+        fs::create_dir(dir.path().join("src")).unwrap();
+        fs::write(
+            dir.path().join("src/lib.rs"),
+            "mod a;\nmod b;\n\nfn main() {}\n",
+        )
+        .unwrap();
+
+        let patch = "\
+diff --git a/src/lib.rs b/src/lib.rs
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -1,3 +1,4 @@
+ mod a;
+ mod b;
++mod c;
+ 
+--- /dev/null
++++ b/src/c.rs
+@@ -0,0 +1,2 @@
++pub fn c() {}
++// new
+";
+        let result = handle_command(patch_input(dir.path(), patch)).unwrap();
+
+        assert_success_contains(&result, "hunk 1 @@ -1,3 +1,4 @@ applied at line 1");
+        assert_eq!(
+            fs::read_to_string(dir.path().join("src/lib.rs")).unwrap(),
+            "mod a;\nmod b;\nmod c;\n\nfn main() {}\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.path().join("src/c.rs")).unwrap(),
+            "pub fn c() {}\n// new\n"
+        );
+    }
+
+    #[test]
+    fn test_apply_patch_with_offset() {
+        let file = create_test_file("one\ntwo\nthree\nfour\nfive\nsix");
+        // The hunk claims line 1, but its lines start at line 4
+        let patch = "--- a/f\n+++ b/f\n@@ -1,2 +1,2 @@\n four\n-five\n+FIVE\n";
+
+        let mut input = patch_input(file.path(), patch);
+        input.max_offset = Some(2);
+        let result = handle_command(input);
+        assert!(matches!(result, Err(EditorError::PatchRejected(_))));
+
+        let result = handle_command(patch_input(file.path(), patch)).unwrap();
+        assert_success_contains(&result, "applied at line 4 (offset 3 lines)");
+        verify_file_content(file.path(), "one\ntwo\nthree\nfour\nFIVE\nsix");
+    }
+
+    #[test]
+    fn test_apply_patch_with_fuzz() {
+        let file = create_test_file("one\ntwo\nthree\nfour");
+        // The first context line does not match the file
+        let patch = "--- a/f\n+++ b/f\n@@ -1,3 +1,3 @@\n uno\n two\n-three\n+THREE\n";
+
+        let result = handle_command(patch_input(file.path(), patch));
+        assert!(matches!(result, Err(EditorError::PatchRejected(_))));
+
+        let mut input = patch_input(file.path(), patch);
+        input.fuzz = Some(1);
+        let result = handle_command(input).unwrap();
+        assert_success_contains(&result, "applied at line 2 with fuzz 1");
+        verify_file_content(file.path(), "one\ntwo\nTHREE\nfour");
+    }
+
+    #[test]
+    fn test_rejected_hunk_writes_nothing() {
+        let file = create_test_file("alpha\nbeta\ngamma");
+        let patch = "\
+--- a/f
++++ b/f
+@@ -1,1 +1,1 @@
+-alpha
++ALPHA
+@@ -3,1 +3,1 @@
+-delta
++DELTA
+";
+        let err = handle_command(patch_input(file.path(), patch)).unwrap_err();
+
+        let message = err.to_string();
+        assert_success_contains(&message, "hunk 1 @@ -1,1 +1,1 @@ applied at line 1");
+        assert_success_contains(
+            &message,
+            "hunk 2 @@ -3,1 +3,1 @@ rejected: its context and removed lines were not found near line 3",
+        );
+        verify_file_content(file.path(), "alpha\nbeta\ngamma");
+    }
+
+    #[test]
+    fn test_patch_removes_trailing_newline() {
+        let file = create_test_file("first\nlast");
+        let patch = "--- a/f\n+++ b/f\n@@ -2 +2 @@\n-last\n+final\n\\ No newline at end of file\n";
+
+        handle_command(patch_input(file.path(), patch)).unwrap();
+
+        assert_eq!(fs::read_to_string(file.path()).unwrap(), "first\nfinal");
+    }
+
+    #[test]
+    fn test_patch_rename_does_not_overwrite() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("f.txt"), "move\n").unwrap();
+        fs::write(dir.path().join("g.txt"), "keep\n").unwrap();
+        let patch = "--- a/f.txt\n+++ b/g.txt\n@@ -1 +1 @@\n-move\n+moved\n";
+
+        let result = handle_command(patch_input(dir.path(), patch));
+
+        assert!(matches!(result, Err(EditorError::PathAlreadyExists(_))));
+        assert_eq!(
+            fs::read_to_string(dir.path().join("f.txt")).unwrap(),
+            "move\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.path().join("g.txt")).unwrap(),
+            "keep\n"
+        );
+    }
+}
+
+mod file_management_tests {
+    use super::*;
+    use crate::history::History;
+
+    fn path_input(command: &str, path: &Path, new_path: Option<&Path>) -> Input {
+        let mut input = create_test_input(command, path.to_str().unwrap()).input;
+        input.new_path = new_path.map(|p| p.to_string_lossy().to_string());
+        input
+    }
+
+    #[test]
+    fn test_rename_into_new_directory() {
+        let dir = tempdir().unwrap();
+        let from = dir.path().join("old.rs");
+        let to = dir.path().join("module/new.rs");
+        fs::write(&from, "fn main() {}\n").unwrap();
+
+        let result = handle_command(path_input("rename", &from, Some(&to))).unwrap();
+
+        assert_success_contains(&result, "Renamed");
+        assert!(!from.exists());
+        assert_eq!(fs::read_to_string(&to).unwrap(), "fn main() {}\n");
+    }
+
+    #[test]
+    fn test_rename_and_copy_refuse_to_overwrite() {
+        let dir = tempdir().unwrap();
+        let from = dir.path().join("a.txt");
+        let to = dir.path().join("b.txt");
+        fs::write(&from, "a").unwrap();
+        fs::write(&to, "b").unwrap();
+
+        for command in ["rename", "copy"] {
+            let result = handle_command(path_input(command, &from, Some(&to)));
+            assert!(matches!(result, Err(EditorError::PathAlreadyExists(_))));
+        }
+        assert!(matches!(
+            handle_command(path_input("copy", &from, None)),
+            Err(EditorError::MissingNewPath(Command::Copy))
+        ));
+        assert_eq!(fs::read_to_string(&to).unwrap(), "b");
+    }
+
+    #[test]
+    fn test_copy_file() {
+        let dir = tempdir().unwrap();
+        let from = dir.path().join("a.txt");
+        let to = dir.path().join("copy/a.txt");
+        fs::write(&from, "content").unwrap();
+
+        handle_command(path_input("copy", &from, Some(&to))).unwrap();
+
+        assert_eq!(fs::read_to_string(&from).unwrap(), "content");
+        assert_eq!(fs::read_to_string(&to).unwrap(), "content");
+
+        // Only files can be copied
+        let result = handle_command(path_input("copy", dir.path(), Some(&dir.path().join("x"))));
+        assert!(matches!(
+            result,
+            Err(EditorError::IsADirectory(_, Command::Copy))
+        ));
+    }
+
+    #[test]
+    fn test_removed_file_can_be_undone() {
+        let state_dir = tempdir().unwrap();
+        let editor = Editor {
+            history: Some(History::new(state_dir.path())),
+            ..Default::default()
+        };
+        let file = create_test_file("keep me");
+        let path = file.path().to_path_buf();
+
+        let request = create_test_input("remove", path.to_str().unwrap());
+        editor.handle_request(request).unwrap();
+        assert!(!path.exists());
+
+        let request = create_test_input("undo_edit", path.to_str().unwrap());
+        editor.handle_request(request).unwrap();
+        verify_file_content(&path, "keep me");
+    }
+
+    #[test]
+    fn test_remove_directory_requires_recursive() {
+        let dir = tempdir().unwrap();
+        let target = dir.path().join("build");
+        fs::create_dir(&target).unwrap();
+        fs::write(target.join("out.o"), "").unwrap();
+
+        let result = handle_command(path_input("remove", &target, None));
+        assert!(matches!(result, Err(EditorError::DirectoryNotEmpty(_))));
+        assert!(target.exists());
+
+        let mut input = path_input("remove", &target, None);
+        input.recursive = Some(true);
+        handle_command(input).unwrap();
+        assert!(!target.exists());
+    }
+
+    #[test]
+    fn test_mkdir() {
+        let dir = tempdir().unwrap();
+        let nested = dir.path().join("a/b/c");
+
+        let result = handle_command(path_input("mkdir", &nested, None)).unwrap();
+        assert_success_contains(&result, "Created the directory");
+        assert!(nested.is_dir());
+
+        let result = handle_command(path_input("mkdir", &nested, None));
+        assert!(matches!(result, Err(EditorError::PathAlreadyExists(_))));
+    }
+
+    #[test]
+    fn test_dry_run_rename_moves_nothing() {
+        let dir = tempdir().unwrap();
+        let from = dir.path().join("old.rs");
+        let to = dir.path().join("new.rs");
+        fs::write(&from, "").unwrap();
+
+        let mut input = path_input("rename", &from, Some(&to));
+        input.dry_run = Some(true);
+        let result = handle_command(input).unwrap();
+
+        assert_success_contains(&result, "This is a dry run");
+        assert!(from.exists());
+        assert!(!to.exists());
+    }
+}
+
+mod match_mode_tests {
+    use super::*;
+
+    #[test]
+    fn test_exact_mode_by_default() {
+        let file = create_test_file("let x = 1;   \nlet y = 2;");
+
+        let input = str_replace_input(file.path(), "let x = 1;\n", "");
+        let result = handle_command(input);
+
+        assert!(matches!(result, Err(EditorError::StrReplace(_))));
+    }
+
+    #[test]
+    fn test_ignore_trailing_whitespace() {
+        let file = create_test_file("let x = 1;   \nlet y = 2;\t\nlet z = 3;");
+
+        let mut input = str_replace_input(file.path(), "let x = 1;\nlet y = 2;", "let xy = 3;");
+        input.match_mode = Some(MatchMode::IgnoreTrailingWs);
+        let result = handle_command(input).unwrap();
+
+        assert_success_contains(&result, "matched ignoring trailing whitespace");
+        verify_file_content(file.path(), "let xy = 3;\nlet z = 3;");
+    }
+
+    #[test]
+    fn test_indentation_insensitive_reindents() {
+        let file = create_test_file("impl A {\n\tfn f() {\n\t\tg();\n\t}\n}");
+
+        // The model used four spaces, and the file uses tabs
+        let mut input = str_replace_input(
+            file.path(),
+            "fn f() {\n    g();\n}",
+            "fn f() {\n    g();\n    h();\n}",
+        );
+        input.match_mode = Some(MatchMode::IndentationInsensitive);
+        handle_command(input).unwrap();
+
+        verify_file_content(
+            file.path(),
+            "impl A {\n\tfn f() {\n\t\tg();\n\t\th();\n\t}\n}",
+        );
+    }
+
+    #[test]
+    fn test_relaxed_match_must_be_unique() {
+        let file = create_test_file("  a();\n  b();\n    a();\n    b();");
+
+        let mut input = str_replace_input(file.path(), "a();\n b();", "c();");
+        input.match_mode = Some(MatchMode::IndentationInsensitive);
+        let result = handle_command(input);
+
+        match result {
+            Err(EditorError::StrReplace(message)) => {
+                assert_success_contains(&message, "matches 2 places when ignoring indentation");
+            }
+            other => panic!("expected an ambiguous match, got {:?}", other),
+        }
+    }
+}
+
+mod not_found_tests {
+    use super::*;
+
+    #[test]
+    fn test_not_found_shows_closest_lines() {
+        let file = create_test_file("fn main() {\n    let x = foo(1);\n    bar();\n}");
+
+        let result = handle_command(str_replace_input(
+            file.path(),
+            "    let x = foo();\n    bar();",
+            "",
+        ));
+
+        let message = result.unwrap_err().to_string();
+        assert_success_contains(&message, "The string was not found in the file.");
+        assert_success_contains(&message, "Lines 2-3 (");
+        assert_success_contains(
+            &message,
+            "-    let x = foo();\n+    let x = foo(1);\n     bar();",
+        );
+    }
+
+    #[test]
+    fn test_not_found_without_similar_lines() {
+        let file = create_test_file("alpha\nbeta");
+
+        let result = handle_command(str_replace_input(
+            file.path(),
+            "completely unrelated text",
+            "",
+        ));
+
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "The string was not found in the file."
+        );
+    }
+
+    #[test]
+    fn test_not_found_in_large_file() {
+        let lines: Vec<String> = (0..20_000)
+            .map(|i| format!("    let value_{} = compute({}, \"item {}\");", i, i * 7, i))
+            .collect();
+        let file = create_test_file(&lines.join("\n"));
+        let mut old_lines = lines[10_000..10_040].to_vec();
+        old_lines[20] = old_lines[20].replace("compute", "compte");
+
+        let started = std::time::Instant::now();
+        let result = handle_command(str_replace_input(file.path(), &old_lines.join("\n"), ""));
+
+        assert!(started.elapsed() < std::time::Duration::from_secs(5));
+        let message = result.unwrap_err().to_string();
+        assert_success_contains(&message, "Lines 10001-10040 (");
+    }
+}
+
+mod ambiguous_match_tests {
+    use super::*;
+
+    #[test]
+    fn test_ambiguous_match_lists_lines() {
+        let file = create_test_file("fn a() {\n    log();\n}\nfn b() {\n    log();\n}");
+
+        let result = handle_command(str_replace_input(file.path(), "log();", ""));
+
+        let message = result.unwrap_err().to_string();
+        assert_success_contains(&message, "(2 occurrences)");
+        assert_success_contains(
+            &message,
+            "The matches are on these lines:\n     2\tlog();\n     5\tlog();",
+        );
+    }
+
+    #[test]
+    fn test_ambiguous_regex_locations_are_capped() {
+        let file = create_test_file("x1\nx2\nx3\nx4");
+
+        let mut input = str_replace_input(file.path(), r"x\d", "");
+        input.use_regex = Some(true);
+        input.max_locations = Some(2);
+        let result = handle_command(input);
+
+        let message = result.unwrap_err().to_string();
+        assert_success_contains(&message, "     1\tx1\n     2\tx2\n... and 2 more");
+    }
+}
+
+mod occurrence_tests {
+    use super::*;
+
+    #[test]
+    fn test_replace_second_occurrence() {
+        let file = create_test_file("x = 1\nx = 1\nx = 1");
+
+        let mut input = str_replace_input(file.path(), "x = 1", "x = 2");
+        input.occurrence = Some(2);
+        let result = handle_command(input).unwrap();
+
+        assert_success_contains(&result, "The replacement starts at line 2.");
+        verify_file_content(file.path(), "x = 1\nx = 2\nx = 1");
+    }
+
+    #[test]
+    fn test_occurrence_out_of_bounds() {
+        let file = create_test_file("x = 1\nx = 1");
+
+        let mut input = str_replace_input(file.path(), "x = 1", "x = 2");
+        input.occurrence = Some(3);
+        let result = handle_command(input);
+
+        let message = result.unwrap_err().to_string();
+        assert_success_contains(
+            &message,
+            "Invalid `occurrence` parameter: 3. There are 2 matches",
+        );
+    }
+
+    #[test]
+    fn test_line_range_restricts_regex() {
+        let file = create_test_file("let a = 1;\nfn f() {\n    let a = 1;\n}");
+
+        let mut input = str_replace_input(file.path(), r"let (\w+) = 1;", "let $1 = 2;");
+        input.use_regex = Some(true);
+        input.line_range = Some(vec![2, 4]);
+        let result = handle_command(input).unwrap();
+
+        assert_success_contains(&result, "The replacement starts at line 3.");
+        verify_file_content(file.path(), "let a = 1;\nfn f() {\n    let a = 2;\n}");
+    }
+
+    #[test]
+    fn test_no_match_in_line_range() {
+        let file = create_test_file("target\nother\nother");
+
+        let mut input = str_replace_input(file.path(), "target", "x");
+        input.line_range = Some(vec![2, 3]);
+        let result = handle_command(input);
+
+        let message = result.unwrap_err().to_string();
+        assert_success_contains(&message, "No match was found in lines 2-3.");
+        assert_success_contains(&message, "     1\ttarget");
+    }
+}
+
+mod replace_lines_tests {
+    use super::*;
+
+    fn replace_lines_input(path: &Path, range: Vec<i32>, new_str: &str) -> Input {
+        let mut input = create_test_input("replace_lines", path.to_str().unwrap()).input;
+        input.line_range = Some(range);
+        input.new_str = Some(new_str.to_string());
+        input
+    }
+
+    #[test]
+    fn test_replace_lines() {
+        let file = create_test_file("one\ntwo\nthree\nfour");
+
+        let result = handle_command(replace_lines_input(
+            file.path(),
+            vec![2, 3],
+            "TWO\nTHREE\nMORE",
+        ))
+        .unwrap();
+
+        assert_success_contains(&result, "Replaced lines 2-3.");
+        assert_success_contains(
+            &result,
+            "     2\tTWO\n     3\tTHREE\n     4\tMORE\n     5\tfour",
+        );
+        verify_file_content(file.path(), "one\nTWO\nTHREE\nMORE\nfour");
+    }
+
+    #[test]
+    fn test_replace_lines_end_past_file() {
+        let file = create_test_file("one\ntwo");
+
+        handle_command(replace_lines_input(file.path(), vec![2, 10], "last")).unwrap();
+
+        assert_eq!(fs::read_to_string(file.path()).unwrap(), "one\nlast\n");
+    }
+
+    #[test]
+    fn test_replace_lines_invalid_range() {
+        let file = create_test_file("one\ntwo");
+
+        let result = handle_command(replace_lines_input(file.path(), vec![3, 4], "x"));
+
+        match result {
+            Err(EditorError::InvalidRange(message)) => {
+                assert!(message.starts_with("Invalid line_range: [3, 4]."));
+            }
+            other => panic!("expected an invalid range, got {:?}", other),
+        }
+    }
+}
+
+mod directory_listing_tests {
+    use super::*;
+
+    fn view_dir(dir: &Path) -> Input {
+        create_test_input("view", dir.to_str().unwrap()).input
+    }
+
+    #[test]
+    fn test_view_respects_ignore_files() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join(".gitignore"), "target/\n*.log\n").unwrap();
+        fs::write(dir.path().join(".ignore"), "vendor/\n").unwrap();
+        for sub in ["src", "target", "vendor"] {
+            fs::create_dir(dir.path().join(sub)).unwrap();
+            fs::write(dir.path().join(sub).join("file.rs"), "").unwrap();
+        }
+        fs::write(dir.path().join("debug.log"), "").unwrap();
+
+        let result = handle_command(view_dir(dir.path())).unwrap();
+
+        assert_success_contains(&result, "src/file.rs");
+        assert!(!result.contains("target"));
+        assert!(!result.contains("vendor"));
+        assert!(!result.contains("debug.log"));
+        assert!(!result.contains(".gitignore"));
+    }
+
+    #[test]
+    fn test_long_format() {
+        let dir = tempdir().unwrap();
+        fs::create_dir(dir.path().join("src")).unwrap();
+        fs::write(dir.path().join("src/main.rs"), "fn main() {\n}\n").unwrap();
+
+        let mut input = view_dir(dir.path());
+        input.format = Some(ListFormat::Long);
+        let result = handle_command(input).unwrap();
+
+        let root = dir.path().display();
+        assert_eq!(
+            result,
+            format!("{root}/src/\n{root}/src/main.rs (14 bytes, 2 lines)")
+        );
+    }
+
+    #[test]
+    fn test_long_format_counts_lines_of_large_files() {
+        let dir = tempdir().unwrap();
+        // Multi-byte characters straddle the blocks the file is read in
+        let text = "héllo wörld ✓\n".repeat(3000) + "no newline";
+        fs::write(dir.path().join("big.txt"), &text).unwrap();
+        fs::write(dir.path().join("data.bin"), [0xff, 0xfe, b'\n']).unwrap();
+
+        let mut input = view_dir(dir.path());
+        input.format = Some(ListFormat::Long);
+        let result = handle_command(input).unwrap();
+
+        let root = dir.path().display();
+        assert_eq!(
+            result,
+            format!(
+                "{root}/big.txt ({} bytes, 3001 lines)\n{root}/data.bin (3 bytes)",
+                text.len()
+            )
+        );
+    }
+
+    #[test]
+    fn test_tree_format() {
+        let dir = tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("src/nested/deeper")).unwrap();
+        fs::write(dir.path().join("src/main.rs"), "").unwrap();
+        fs::write(dir.path().join("src/nested/deeper/a.rs"), "").unwrap();
+        fs::write(dir.path().join("src/nested/deeper/b.rs"), "").unwrap();
+        fs::write(dir.path().join("README.md"), "").unwrap();
+
+        let mut input = view_dir(dir.path());
+        input.format = Some(ListFormat::Tree);
+        input.max_depth = Some(2);
+        let result = handle_command(input).unwrap();
+
+        assert_eq!(
+            result,
+            format!(
+                "{}/\nREADME.md\nsrc/\n  main.rs\n  nested/\n    deeper/ (2 entries)",
+                dir.path().display()
+            )
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_special_files_are_not_followed() {
+        let dir = tempdir().unwrap();
+        let outside = tempdir().unwrap();
+        fs::write(outside.path().join("secret.txt"), "secret\n").unwrap();
+        std::os::unix::fs::symlink(outside.path(), dir.path().join("link")).unwrap();
+        std::os::unix::fs::symlink(outside.path().join("secret.txt"), dir.path().join("slink"))
+            .unwrap();
+        // Opening a named pipe would block until something writes to it
+        let status = std::process::Command::new("mkfifo")
+            .arg(dir.path().join("pipe"))
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let mut input = view_dir(dir.path());
+        input.format = Some(ListFormat::Long);
+        let result = handle_command(input).unwrap();
+
+        let root = dir.path().display();
+        assert_eq!(result, format!("{root}/link@\n{root}/pipe\n{root}/slink@"));
+
+        let mut input = view_dir(dir.path());
+        input.format = Some(ListFormat::Tree);
+        input.max_depth = Some(0);
+        let result = handle_command(input).unwrap();
+
+        assert_eq!(result, format!("{root}/\nlink@\npipe\nslink@"));
+    }
+
+    #[test]
+    fn test_entries_are_capped() {
+        let dir = tempdir().unwrap();
+        for i in 0..5 {
+            fs::write(dir.path().join(format!("file{}.txt", i)), "").unwrap();
+        }
+
+        let mut input = view_dir(dir.path());
+        input.max_entries = Some(2);
+        let result = handle_command(input).unwrap();
+
+        assert_success_contains(&result, "file0.txt\n");
+        assert_success_contains(&result, "file1.txt\n3 more entries omitted");
+        assert!(!result.contains("file2.txt"));
+    }
+}
+
+mod search_tests {
+    use super::*;
+
+    fn search_input(path: &Path, pattern: &str) -> Input {
+        let mut input = create_test_input("search", path.to_str().unwrap()).input;
+        input.pattern = Some(pattern.to_string());
+        input
+    }
+
+    fn sample_tree() -> tempfile::TempDir {
+        let dir = tempdir().unwrap();
+        fs::create_dir(dir.path().join("src")).unwrap();
+        fs::write(
+            dir.path().join("src/main.rs"),
+            "fn main() {\n    helper();\n}\n\nfn helper() {}\n",
+        )
+        .unwrap();
+        fs::write(dir.path().join("notes.md"), "call helper() here\n").unwrap();
+        fs::write(dir.path().join(".gitignore"), "build/\n").unwrap();
+        fs::create_dir(dir.path().join("build")).unwrap();
+        fs::write(dir.path().join("build/out.rs"), "helper();\n").unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_search_directory() {
+        let dir = sample_tree();
+
+        let result = handle_command(search_input(dir.path(), r"helper\(\)")).unwrap();
+
+        let root = dir.path().display();
+        assert_eq!(
+            result,
+            format!(
+                "Found 3 matches for `helper\\(\\)` in 2 files:\n\
+                 {root}/notes.md:1\n     1\tcall helper() here\n\
+                 {root}/src/main.rs:2\n     2\t    helper();\n\
+                 {root}/src/main.rs:5\n     5\tfn helper() {{}}"
+            )
+        );
+    }
+
+    #[test]
+    fn test_search_with_globs_and_context() {
+        let dir = sample_tree();
+
+        let mut input = search_input(dir.path(), "helper");
+        input.include = Some(vec!["*.rs".to_string()]);
+        input.context_lines = Some(2);
+        let result = handle_command(input).unwrap();
+
+        assert!(!result.contains("notes.md"));
+        assert!(!result.contains("build"));
+        // The two matches' context overlaps, so they are shown together
+        assert_success_contains(
+            &result,
+            "src/main.rs:2,5\n     1\tfn main() {\n     2\t    helper();\n     3\t}\n     4\t\n     5\tfn helper() {}",
+        );
+
+        let mut input = search_input(dir.path(), "helper");
+        input.exclude = Some(vec!["src/**".to_string()]);
+        let result = handle_command(input).unwrap();
+        assert_success_contains(&result, "in 1 files");
+    }
+
+    #[test]
+    fn test_search_results_are_capped() {
+        let dir = sample_tree();
+
+        let mut input = search_input(dir.path(), "helper");
+        input.max_results = Some(2);
+        let result = handle_command(input).unwrap();
+
+        assert_success_contains(&result, "Found 2 matches");
+        assert_success_contains(&result, "Results were capped at 2 matches.");
+    }
+
+    #[test]
+    fn test_search_cap_reached_by_earlier_file() {
+        let dir = tempdir().unwrap();
+        fs::write(
+            dir.path().join("a.txt"),
+            "foo
+",
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("b.txt"),
+            "foo
+",
+        )
+        .unwrap();
+
+        let mut input = search_input(dir.path(), "foo");
+        input.max_results = Some(1);
+        let result = handle_command(input).unwrap();
+
+        assert_success_contains(&result, "Found 1 matches for `foo` in 1 files");
+        assert!(!result.contains("b.txt"));
+        assert_success_contains(&result, "Results were capped at 1 matches.");
+    }
+
+    #[test]
+    fn test_search_no_matches() {
+        let file = create_test_file("nothing here");
+
+        let result = handle_command(search_input(file.path(), "missing")).unwrap();
+
+        assert_success_contains(&result, "No matches found for `missing`");
+    }
+
+    #[test]
+    fn test_search_invalid_regex() {
+        let file = create_test_file("content");
+
+        let result = handle_command(search_input(file.path(), "fn ("));
+
+        let message = result.unwrap_err().to_string();
+        assert!(message.starts_with("Invalid regex pattern: regex parse error"));
+    }
+}
+
+mod find_tests {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+
+    fn find_input(path: &Path, pattern: &str) -> Input {
+        let mut input = create_test_input("find", path.to_str().unwrap()).input;
+        input.pattern = Some(pattern.to_string());
+        input
+    }
+
+    fn write_with_age(path: &Path, age_secs: u64) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let file = File::create(path).unwrap();
+        file.set_modified(SystemTime::now() - Duration::from_secs(age_secs))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_find_by_glob_sorted_by_name() {
+        let dir = tempdir().unwrap();
+        write_with_age(&dir.path().join("src/lib.rs"), 0);
+        write_with_age(&dir.path().join("src/net/mod.rs"), 0);
+        write_with_age(&dir.path().join("src/net/tcp.rs"), 0);
+        write_with_age(&dir.path().join("README.md"), 0);
+        write_with_age(&dir.path().join("target/debug/build.rs"), 0);
+        fs::write(dir.path().join(".gitignore"), "target/\n").unwrap();
+
+        let mut input = find_input(dir.path(), "**/*.rs");
+        input.sort_by = Some(SortOrder::Name);
+        let result = handle_command(input).unwrap();
+
+        let root = dir.path().display();
+        assert_eq!(
+            result,
+            format!(
+                "Found 3 files matching `**/*.rs`:\n{root}/src/lib.rs\n{root}/src/net/mod.rs\n{root}/src/net/tcp.rs"
+            )
+        );
+
+        let result = handle_command(find_input(dir.path(), "src/**/mod.rs")).unwrap();
+        assert_success_contains(&result, "Found 1 files");
+    }
+
+    #[test]
+    fn test_find_sorted_by_modified_with_limit() {
+        let dir = tempdir().unwrap();
+        write_with_age(&dir.path().join("old.txt"), 300);
+        write_with_age(&dir.path().join("newest.txt"), 0);
+        write_with_age(&dir.path().join("middle.txt"), 100);
+
+        let mut input = find_input(dir.path(), "*.txt");
+        input.max_results = Some(2);
+        let result = handle_command(input).unwrap();
+
+        let root = dir.path().display();
+        assert_eq!(
+            result,
+            format!(
+                "Found 3 files matching `*.txt`:\n{root}/newest.txt\n{root}/middle.txt\n1 more files omitted"
+            )
+        );
+    }
+
+    #[test]
+    fn test_find_requires_directory() {
+        let file = create_test_file("content");
+
+        let result = handle_command(find_input(file.path(), "*"));
+
+        assert!(matches!(
+            result,
+            Err(EditorError::NotADirectory(_, Command::Find))
+        ));
+    }
+}